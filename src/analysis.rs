@@ -0,0 +1,529 @@
+use std::{collections::HashMap, error, fmt};
+
+use crate::{
+    ast::AST,
+    equation::{solve_with, Equation},
+    functions::FunctionRegistry,
+    roots::{find_root_between, find_root_with, RootOptions},
+    simplifier::EvaluationError,
+    table::align_columns,
+    Error,
+};
+
+#[derive(Debug)]
+pub enum AnalysisError {
+    /// The interval is empty, or one of its ends is not a finite number.
+    InvalidInterval { start: f64, end: f64 },
+}
+impl error::Error for AnalysisError {}
+
+impl fmt::Display for AnalysisError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            AnalysisError::InvalidInterval { start, end } => write!(
+                formatter,
+                "[{}, {}] is not a finite, nonempty interval",
+                start, end
+            ),
+        };
+    }
+}
+
+/// What kind of stationary point a [`CriticalPoint`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointKind {
+    Minimum,
+    Maximum,
+    /// The function keeps rising or falling through the point, as `x^3` does at `0`.
+    Inflection,
+}
+
+impl fmt::Display for PointKind {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        return formatter.write_str(match self {
+            PointKind::Minimum => "local minimum",
+            PointKind::Maximum => "local maximum",
+            PointKind::Inflection => "inflection",
+        });
+    }
+}
+
+/// A point where the first derivative vanishes.
+#[derive(Debug, Clone)]
+pub struct CriticalPoint {
+    pub x: f64,
+    /// The value of the function at `x`.
+    pub value: f64,
+    /// The value of the second derivative at `x`.
+    pub curvature: f64,
+    pub kind: PointKind,
+}
+
+/// Which way a function moves on a [`MonotonicInterval`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trend {
+    Increasing,
+    Decreasing,
+    Constant,
+}
+
+impl fmt::Display for Trend {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        return formatter.write_str(match self {
+            Trend::Increasing => "increasing",
+            Trend::Decreasing => "decreasing",
+            Trend::Constant => "constant",
+        });
+    }
+}
+
+/// A maximal part of the analyzed interval on which the function is monotonic.
+#[derive(Debug, Clone)]
+pub struct MonotonicInterval {
+    pub start: f64,
+    pub end: f64,
+    pub trend: Trend,
+}
+
+impl MonotonicInterval {
+    /// `[start, end]`, with the ends at one of `poles` left open.
+    fn bracketed(&self, poles: &[f64]) -> String {
+        let open = if poles.contains(&self.start) {
+            "("
+        } else {
+            "["
+        };
+        let close = if poles.contains(&self.end) { ")" } else { "]" };
+        return format!(
+            "{}{}, {}{}",
+            open,
+            number(self.start),
+            number(self.end),
+            close
+        );
+    }
+}
+
+/// The result of [`analyze`].
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    pub derivative: AST,
+    pub second_derivative: AST,
+    /// The stationary points strictly inside the interval, in ascending order.
+    pub critical_points: Vec<CriticalPoint>,
+    /// The points strictly inside the interval where the function or its first derivative
+    /// blows up or is undefined, such as `0` for `1/x`, in ascending order.
+    pub poles: Vec<f64>,
+    /// The monotonic parts of the interval, in order and covering all of it. The intervals
+    /// are split at every pole, even where the function falls or rises on both sides of it.
+    pub intervals: Vec<MonotonicInterval>,
+}
+
+/// How many equally spaced points the first derivative is sampled at to find its zeros.
+const SAMPLES: usize = 256;
+/// Values of a derivative that are smaller than this, relative to its largest sampled value
+/// for zeros, count as zero.
+const DERIVATIVE_TOLERANCE: f64 = 1e-9;
+/// Second derivatives smaller than this leave a point to the first derivative test. Looser
+/// than `DERIVATIVE_TOLERANCE`, since double zeros such as that of `1 - cos(x)` at `2 pi` are
+/// only found to about the square root of the working precision.
+const CURVATURE_TOLERANCE: f64 = 1e-6;
+/// How many times a sign change between two samples is bisected to tell a pole from a zero.
+const BISECTIONS: usize = 100;
+
+/// The stationary points of `expr` in `var` strictly between the ends of `interval`,
+/// classified as minima, maxima or inflection points, and the parts of the interval on which
+/// `expr` is increasing, decreasing or constant.
+///
+/// Zeros of the first derivative are found by [`solve`](crate::solve) where it applies, and by
+/// sampling the derivative and refining every sign change and every dip towards zero with
+/// [`find_root`](crate::find_root). Sampling can miss zeros that are closer together than the
+/// sample spacing. A point is classified by the sign of the second derivative, or, where that
+/// vanishes, by whether the first derivative changes sign.
+pub fn analyze(expr: &AST, var: &str, interval: (f64, f64)) -> Result<Analysis, Error> {
+    return analyze_with(expr, var, interval, FunctionRegistry::builtin());
+}
+
+/// [`analyze`] using the functions in `registry`.
+pub fn analyze_with(
+    expr: &AST,
+    var: &str,
+    (start, end): (f64, f64),
+    registry: &FunctionRegistry,
+) -> Result<Analysis, Error> {
+    if !(start < end && start.is_finite() && end.is_finite()) {
+        return Err(AnalysisError::InvalidInterval { start, end }.into());
+    }
+    if let Some(name) = expr.variables().into_iter().find(|name| name != var) {
+        return Err(EvaluationError::UnboundVariable(name).into());
+    }
+    let analyzer = Analyzer {
+        var,
+        start,
+        end,
+        registry,
+    };
+    let derivative = expr.nth_derivative_with(var, 1, registry)?;
+    let second_derivative = derivative.nth_derivative_with(var, 1, registry)?;
+    let stationary = analyzer.zeros(&derivative);
+    let poles = analyzer.poles(&[expr, &derivative]);
+
+    let mut bounds = vec![start];
+    bounds.extend(&stationary);
+    bounds.extend(&poles);
+    bounds.push(end);
+    bounds.sort_by(f64::total_cmp);
+    let trends: Vec<Trend> = bounds
+        .windows(2)
+        .map(|piece| analyzer.trend(&derivative, piece[0], piece[1]))
+        .collect();
+
+    let mut critical_points = Vec::with_capacity(stationary.len());
+    for x in stationary.iter().copied() {
+        // The pieces on either side of `x`.
+        let i = bounds.iter().position(|bound| *bound == x).unwrap_or(1) - 1;
+        let curvature = analyzer.evaluate(&second_derivative, x).unwrap_or(f64::NAN);
+        let kind = if curvature > CURVATURE_TOLERANCE {
+            PointKind::Minimum
+        } else if curvature < -CURVATURE_TOLERANCE {
+            PointKind::Maximum
+        } else {
+            match (trends[i], trends[i + 1]) {
+                (Trend::Decreasing, Trend::Increasing) => PointKind::Minimum,
+                (Trend::Increasing, Trend::Decreasing) => PointKind::Maximum,
+                _ => PointKind::Inflection,
+            }
+        };
+        critical_points.push(CriticalPoint {
+            x,
+            value: analyzer.evaluate(expr, x).unwrap_or(f64::NAN),
+            curvature,
+            kind,
+        });
+    }
+
+    let mut intervals: Vec<MonotonicInterval> = Vec::new();
+    for (piece, trend) in bounds.windows(2).zip(trends) {
+        match intervals.last_mut() {
+            Some(last) if last.trend == trend && !poles.contains(&piece[0]) => last.end = piece[1],
+            _ => intervals.push(MonotonicInterval {
+                start: piece[0],
+                end: piece[1],
+                trend,
+            }),
+        }
+    }
+    return Ok(Analysis {
+        derivative,
+        second_derivative,
+        critical_points,
+        poles,
+        intervals,
+    });
+}
+
+struct Analyzer<'a> {
+    var: &'a str,
+    start: f64,
+    end: f64,
+    registry: &'a FunctionRegistry,
+}
+
+impl Analyzer<'_> {
+    /// The zeros of `derivative` strictly inside the interval, in ascending order.
+    fn zeros(&self, derivative: &AST) -> Vec<f64> {
+        let mut zeros = Vec::new();
+        if let AST::Literal { .. } = derivative {
+            // Constant, so either nowhere or everywhere zero.
+            return zeros;
+        }
+        let equation = Equation {
+            left: derivative.clone(),
+            right: AST::Literal { value: 0. },
+        };
+        // May find only some of the zeros, e.g. `acos(0)` for `cos(x) = 0`.
+        if let Ok(solutions) = solve_with(&equation, self.var, self.registry) {
+            for solution in solutions {
+                if let Ok(x) = solution.evaluate_with(&HashMap::new(), self.registry) {
+                    zeros.push(x);
+                }
+            }
+        }
+        let step = (self.end - self.start) / SAMPLES as f64;
+        let samples: Vec<(f64, Option<f64>)> = (0..=SAMPLES)
+            .map(|i| {
+                let x = self.start + step * i as f64;
+                (x, self.evaluate(derivative, x))
+            })
+            .collect();
+        let options = RootOptions::default();
+        for pair in samples.windows(2) {
+            if let [(a, Some(fa)), (b, Some(fb))] = *pair {
+                if fa == 0. {
+                    zeros.push(a);
+                } else if fb != 0. && fa.signum() != fb.signum() {
+                    zeros.extend(
+                        find_root_between(derivative, self.var, (a, b), &options, self.registry)
+                            .ok(),
+                    );
+                }
+            }
+        }
+        for triple in samples.windows(3) {
+            if let [(_, Some(fa)), (b, Some(fb)), (_, Some(fc))] = *triple {
+                // A dip towards zero without a sign change may be a double zero, as for
+                // `1 - cos(x)` at `0`.
+                let dips = fb.abs() < fa.abs() && fb.abs() < fc.abs();
+                if dips && fa.signum() == fb.signum() && fb.signum() == fc.signum() {
+                    zeros.extend(
+                        find_root_with(derivative, self.var, b, &options, self.registry)
+                            .ok()
+                            .filter(|x| (x - b).abs() <= step),
+                    );
+                }
+            }
+        }
+        let scale = samples
+            .iter()
+            .filter_map(|(_, value)| *value)
+            .fold(1., |scale: f64, value| scale.max(value.abs()));
+        let mut zeros: Vec<f64> = zeros
+            .into_iter()
+            .filter(|x| *x > self.start && *x < self.end)
+            // Turns `-0` into `0`.
+            .map(|x| x + 0.)
+            .filter(|x| {
+                self.evaluate(derivative, *x)
+                    .is_some_and(|value| value.abs() <= DERIVATIVE_TOLERANCE * scale)
+            })
+            .collect();
+        zeros.sort_by(f64::total_cmp);
+        let width = self.start.abs().max(self.end.abs()).max(1.);
+        zeros.dedup_by(|a, b| (*a - *b).abs() <= DERIVATIVE_TOLERANCE * width);
+        return zeros;
+    }
+    /// The points where any of `exprs` is not finite: samples where one is undefined between
+    /// two where it is defined, and sign changes across which `|f|` grows without bound, as
+    /// for `1/x` or `tan(x)`. Longer stretches where a function is undefined, such as
+    /// `ln(x)` for `x <= 0`, are not poles.
+    fn poles(&self, exprs: &[&AST]) -> Vec<f64> {
+        let step = (self.end - self.start) / SAMPLES as f64;
+        let mut poles = Vec::new();
+        for expr in exprs {
+            let samples: Vec<(f64, Option<f64>)> = (0..=SAMPLES)
+                .map(|i| {
+                    let x = self.start + step * i as f64;
+                    (x, self.evaluate(expr, x))
+                })
+                .collect();
+            for triple in samples.windows(3) {
+                if let [(_, Some(_)), (x, None), (_, Some(_))] = *triple {
+                    poles.push(x);
+                }
+            }
+            for pair in samples.windows(2) {
+                if let [(a, Some(fa)), (b, Some(fb))] = *pair {
+                    if fa != 0. && fb != 0. && fa.signum() != fb.signum() {
+                        poles.extend(self.pole_between(expr, (a, fa), (b, fb)));
+                    }
+                }
+            }
+        }
+        poles.sort_by(f64::total_cmp);
+        poles.dedup();
+        return poles;
+    }
+    /// Bisects the sign change of `expr` between `a` and `b` down to adjacent numbers, and
+    /// returns where it ends up if `expr` is not finite there or `|expr|` has grown rather
+    /// than shrunk on both sides.
+    fn pole_between(
+        &self,
+        expr: &AST,
+        (mut a, mut fa): (f64, f64),
+        (mut b, mut fb): (f64, f64),
+    ) -> Option<f64> {
+        let scale = fa.abs().max(fb.abs());
+        for _ in 0..BISECTIONS {
+            let middle = a + (b - a) / 2.;
+            if middle <= a || middle >= b {
+                break;
+            }
+            match self.evaluate(expr, middle) {
+                None => return Some(middle),
+                Some(0.) => return None,
+                Some(value) if value.signum() == fa.signum() => (a, fa) = (middle, value),
+                Some(value) => (b, fb) = (middle, value),
+            }
+        }
+        if fa.abs().min(fb.abs()) > scale {
+            return Some(if fa.abs() > fb.abs() { a } else { b });
+        }
+        return None;
+    }
+    /// Whether `derivative` is positive, negative or zero between `a` and `b`, judged by its
+    /// largest value at a few points inside.
+    fn trend(&self, derivative: &AST, a: f64, b: f64) -> Trend {
+        let slope = [0.25, 0.5, 0.75]
+            .iter()
+            .filter_map(|t| self.evaluate(derivative, a + t * (b - a)))
+            .max_by(|x, y| x.abs().total_cmp(&y.abs()));
+        return match slope {
+            Some(slope) if slope > DERIVATIVE_TOLERANCE => Trend::Increasing,
+            Some(slope) if slope < -DERIVATIVE_TOLERANCE => Trend::Decreasing,
+            _ => Trend::Constant,
+        };
+    }
+    fn evaluate(&self, expr: &AST, x: f64) -> Option<f64> {
+        let env = HashMap::from([(self.var.to_string(), x)]);
+        return expr
+            .evaluate_with(&env, self.registry)
+            .ok()
+            .filter(|value| value.is_finite());
+    }
+}
+
+/// Lays out an [`Analysis`] of a function of `var` as two tables, the critical points and
+/// the monotonic intervals, e.g. for `x^3 - 3x` on `[-3, 3]`:
+///
+/// ```text
+/// f'(x) = 3 * x ^ 2 - 3
+/// f''(x) = 6 * x
+///
+/// x   f(x)  f''(x)  kind
+/// -1  2     -6      local maximum
+/// 1   -2    6       local minimum
+///
+/// interval  f(x) is
+/// [-3, -1]  increasing
+/// [-1, 1]   decreasing
+/// [1, 3]    increasing
+/// ```
+pub fn render_analysis(analysis: &Analysis, var: &str) -> String {
+    let mut output = format!(
+        "f'({var}) = {}\nf''({var}) = {}\n\n",
+        analysis.derivative,
+        analysis.second_derivative,
+        var = var
+    );
+    if analysis.critical_points.is_empty() {
+        output += "no stationary points\n";
+    } else {
+        let mut rows = vec![vec![
+            var.to_string(),
+            format!("f({})", var),
+            format!("f''({})", var),
+            "kind".to_string(),
+        ]];
+        rows.extend(analysis.critical_points.iter().map(|point| {
+            vec![
+                number(point.x),
+                number(point.value),
+                number(point.curvature),
+                point.kind.to_string(),
+            ]
+        }));
+        output += &render_table(&rows);
+    }
+    let mut rows = vec![vec!["interval".to_string(), format!("f({}) is", var)]];
+    for interval in &analysis.intervals {
+        if analysis.poles.contains(&interval.start) {
+            rows.push(vec![number(interval.start), "pole".to_string()]);
+        }
+        rows.push(vec![
+            interval.bracketed(&analysis.poles),
+            interval.trend.to_string(),
+        ]);
+    }
+    output += "\n";
+    output += &render_table(&rows);
+    return output.trim_end().to_string();
+}
+
+/// Left-aligned columns separated by two spaces, one line per row.
+fn render_table(rows: &[Vec<String>]) -> String {
+    let mut table = String::new();
+    for line in align_columns(rows) {
+        table += line.trim_end();
+        table += "\n";
+    }
+    return table;
+}
+
+/// `value` rounded to six decimal places, without trailing zeros.
+fn number(value: f64) -> String {
+    if value.is_nan() {
+        return "undefined".to_string();
+    }
+    let rounded = format!("{:.6}", value);
+    let trimmed = rounded.trim_end_matches('0').trim_end_matches('.');
+    return match trimmed {
+        "-0" => "0".to_string(),
+        _ => trimmed.to_string(),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{analyze, render_analysis, PointKind, Trend};
+    use crate::parse_str;
+
+    /// The intervals of `source`, with their ends rounded to nine decimal places.
+    fn trends(source: &str, interval: (f64, f64)) -> Vec<(f64, f64, Trend)> {
+        let analysis = analyze(&parse_str(source).unwrap(), "x", interval).unwrap();
+        return analysis
+            .intervals
+            .iter()
+            .map(|interval| (round(interval.start), round(interval.end), interval.trend))
+            .collect();
+    }
+
+    fn round(x: f64) -> f64 {
+        return (x * 1e9).round() / 1e9 + 0.;
+    }
+
+    #[test]
+    fn classifies_stationary_points() {
+        let analysis = analyze(&parse_str("x^3 - 3*x").unwrap(), "x", (-3., 3.)).unwrap();
+        let points: Vec<(f64, f64, PointKind)> = analysis
+            .critical_points
+            .iter()
+            .map(|point| (round(point.x), round(point.value), point.kind))
+            .collect();
+        assert_eq!(
+            points,
+            [(-1., 2., PointKind::Maximum), (1., -2., PointKind::Minimum)]
+        );
+        assert_eq!(
+            trends("x^3 - 3*x", (-3., 3.)),
+            [
+                (-3., -1., Trend::Increasing),
+                (-1., 1., Trend::Decreasing),
+                (1., 3., Trend::Increasing)
+            ]
+        );
+        let cubic = analyze(&parse_str("x^3").unwrap(), "x", (-1., 1.)).unwrap();
+        assert_eq!(cubic.critical_points[0].kind, PointKind::Inflection);
+        assert_eq!(trends("x^3", (-1., 1.)), [(-1., 1., Trend::Increasing)]);
+    }
+
+    #[test]
+    fn intervals_are_split_at_poles() {
+        assert_eq!(
+            trends("1/x", (-1., 1.)),
+            [(-1., 0., Trend::Decreasing), (0., 1., Trend::Decreasing)]
+        );
+        assert_eq!(
+            trends("1/x^2", (-1., 2.)),
+            [(-1., 0., Trend::Increasing), (0., 2., Trend::Decreasing)]
+        );
+        let tangent = analyze(&parse_str("tan(x)").unwrap(), "x", (0., 3.)).unwrap();
+        assert_eq!(tangent.poles.len(), 1);
+        assert!((tangent.poles[0] - std::f64::consts::FRAC_PI_2).abs() < 1e-12);
+        let analysis = analyze(&parse_str("1/x").unwrap(), "x", (-1., 1.)).unwrap();
+        assert_eq!(
+            render_analysis(&analysis, "x"),
+            "f'(x) = -1 / x ^ 2\nf''(x) = 2 * x / (x ^ 2) ^ 2\n\nno stationary points\n\n\
+             interval  f(x) is\n[-1, 0)   decreasing\n0         pole\n(0, 1]    decreasing"
+        );
+    }
+}
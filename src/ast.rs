@@ -1,10 +1,26 @@
 use std::{
+    cmp::Ordering,
+    collections::{hash_map::DefaultHasher, BTreeSet, HashMap, HashSet},
+    convert::Infallible,
     fmt::{self, Debug},
-    ops::{Add, Div, Mul, Sub},
+    hash::{Hash, Hasher},
+    mem,
+    ops::{Add, Div, Mul, Neg, Sub},
+    rc::Rc,
 };
 
-use crate::tokens::BinOp;
+use crate::{functions::FunctionRegistry, tokens::BinOp};
 
+/// Constants that evaluate to less than this are taken to be zero, since e.g. `sin(pi)` does
+/// not evaluate to exactly `0`.
+pub(crate) const ZERO_TOLERANCE: f64 = 1e-12;
+/// Most continued fraction steps [`as_fraction`] tries, so that irrational values are not
+/// forced into a fraction with a huge numerator and denominator.
+const MAX_FRACTION_STEPS: usize = 12;
+
+/// An expression. Operands of a `BinOp` and arguments of a `Function` are reference counted,
+/// so one subexpression can be shared by several parents and the expression forms a DAG
+/// rather than a tree; cloning copies only the top node.
 pub enum AST {
     Literal {
         value: f64,
@@ -17,31 +33,31 @@ pub enum AST {
     },
     Function {
         name: String,
-        args: Vec<AST>,
+        args: Vec<Rc<AST>>,
     },
     BinOp {
         op: BinOp,
-        left: Box<AST>,
-        right: Box<AST>,
+        left: Rc<AST>,
+        right: Rc<AST>,
     },
 }
 
 impl AST {
     fn tree_str(&self) -> String {
-        match self {
+        return self.fold(|node, children: Vec<String>| match node {
             AST::Literal { value } => value.to_string(),
             AST::SymbolicConstant { name } => name.to_string(),
             AST::Variable { name } => name.to_string(),
-            AST::BinOp { op, left, right } => {
+            AST::BinOp { op, .. } => {
                 let mut s = format!("{:?}\n", op);
-                for (i, line) in left.tree_str().lines().enumerate() {
+                for (i, line) in children[0].lines().enumerate() {
                     s += &format!(
                         "{prefix}{line}\n",
                         prefix = &(if i == 0 { " ├─" } else { " │  " }),
                         line = line
                     );
                 }
-                for (i, line) in right.tree_str().lines().enumerate() {
+                for (i, line) in children[1].lines().enumerate() {
                     s += &format!(
                         "{prefix}{line}\n",
                         prefix = &(if i == 0 { " ╰─" } else { "    " }),
@@ -52,8 +68,8 @@ impl AST {
             }
             AST::Function { name, args } => {
                 let mut s = name.clone() + "\n";
-                for (arg_no, arg) in args.iter().enumerate() {
-                    for (line_no, line) in arg.tree_str().lines().enumerate() {
+                for (arg_no, arg) in children.iter().enumerate() {
+                    for (line_no, line) in arg.lines().enumerate() {
                         s += &format!(
                             "{prefix}{line}\n",
                             prefix = &(if line_no == 0 {
@@ -71,49 +87,180 @@ impl AST {
                 }
                 s
             }
-        }
+        });
     }
     fn flat_str(&self) -> String {
-        match self {
+        return self.fold(|node, children: Vec<String>| match node {
             AST::Literal { value } => value.to_string(),
             AST::SymbolicConstant { name } => name.to_string(),
             AST::Variable { name } => name.to_string(),
             AST::BinOp { op, left, right } => {
-                let is_paren_required_for_left = is_paren_required(*op, &left);
-                let is_paren_required_for_right = is_paren_required(*op, &right);
+                let is_paren_required_for_left = is_paren_required(*op, left, true);
+                let is_paren_required_for_right = is_paren_required(*op, right, false);
                 format!(
                     "{}{}{} {} {}{}{}",
                     if is_paren_required_for_left { "(" } else { "" },
-                    left.flat_str(),
+                    children[0],
                     if is_paren_required_for_left { ")" } else { "" },
                     op,
                     if is_paren_required_for_right { "(" } else { "" },
-                    right.flat_str(),
+                    children[1],
                     if is_paren_required_for_right { ")" } else { "" }
                 )
             }
-            AST::Function { name, args } => format!(
-                "{}({})",
-                name.to_string(),
-                args.iter().map(|arg| arg.flat_str()).collect::<Vec<String>>().join(", ")
-            ),
-        }
+            AST::Function { name, .. } => format!("{}({})", name, children.join(", ")),
+        });
     }
-    pub fn pow(self, rhs: Self) -> AST {
+    /// Builds `left op right`. Either operand may be an `Rc` already shared elsewhere.
+    pub fn binary(op: BinOp, left: impl Into<Rc<AST>>, right: impl Into<Rc<AST>>) -> AST {
         return AST::BinOp {
-            op: BinOp::Pow,
-            left: Box::new(self),
-            right: Box::new(rhs),
+            op,
+            left: left.into(),
+            right: right.into(),
+        };
+    }
+    pub fn pow(self, rhs: impl Into<Rc<AST>>) -> AST {
+        return AST::binary(BinOp::Pow, self, rhs);
+    }
+    /// Whether `var` occurs anywhere in the expression.
+    pub fn contains_variable(&self, var: &str) -> bool {
+        return self.fold(|node, children: Vec<bool>| match node {
+            AST::Variable { name } => name == var,
+            _ => children.into_iter().any(|contains| contains),
+        });
+    }
+    /// Names of all variables occurring in the expression.
+    pub fn variables(&self) -> BTreeSet<String> {
+        let mut variables = BTreeSet::new();
+        let mut visited: HashSet<*const AST> = HashSet::new();
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            if let AST::Variable { name } = node {
+                variables.insert(name.clone());
+            }
+            stack.extend(
+                node.links()
+                    .into_iter()
+                    .filter(|(child, is_shared)| !is_shared || visited.insert(*child))
+                    .map(|(child, _)| child),
+            );
+        }
+        return variables;
+    }
+    /// A copy with every occurrence of the variable `var` replaced by `value`.
+    pub fn substitute(&self, var: &str, value: &AST) -> AST {
+        let value = Rc::new(value.clone());
+        let substituted = self.fold(|node, children: Vec<Rc<AST>>| match node {
+            AST::Variable { name } if name == var => Rc::clone(&value),
+            _ => Rc::new(node.with_children(children)),
+        });
+        return Rc::unwrap_or_clone(substituted);
+    }
+    /// The operands of a `BinOp` or the arguments of a `Function`, in order.
+    pub fn children(&self) -> Vec<&AST> {
+        return match self {
+            AST::Literal { .. } | AST::SymbolicConstant { .. } | AST::Variable { .. } => Vec::new(),
+            AST::Function { args, .. } => args.iter().map(|arg| &**arg).collect(),
+            AST::BinOp { left, right, .. } => vec![left, right],
+        };
+    }
+    /// Like [`AST::children`], but also telling whether each child may be referenced from
+    /// elsewhere too.
+    pub(crate) fn links(&self) -> Vec<(&AST, bool)> {
+        return match self {
+            AST::Literal { .. } | AST::SymbolicConstant { .. } | AST::Variable { .. } => Vec::new(),
+            AST::Function { args, .. } => args
+                .iter()
+                .map(|arg| (&**arg, Rc::strong_count(arg) > 1))
+                .collect(),
+            AST::BinOp { left, right, .. } => vec![
+                (left, Rc::strong_count(left) > 1),
+                (right, Rc::strong_count(right) > 1),
+            ],
+        };
+    }
+    /// A node of the same kind, name and operator as this one, but with `children` in place
+    /// of its own.
+    pub(crate) fn with_children<C: Into<Rc<AST>>>(&self, children: Vec<C>) -> AST {
+        return match self {
+            AST::Literal { value } => AST::Literal { value: *value },
+            AST::SymbolicConstant { name } => AST::SymbolicConstant { name: name.clone() },
+            AST::Variable { name } => AST::Variable { name: name.clone() },
+            AST::Function { name, .. } => AST::Function {
+                name: name.clone(),
+                args: children.into_iter().map(Into::into).collect(),
+            },
+            AST::BinOp { op, .. } => {
+                let mut children = children.into_iter();
+                AST::binary(*op, children.next().unwrap(), children.next().unwrap())
+            }
+        };
+    }
+    /// Computes a value for every node bottom-up, passing `f` each node together with the
+    /// values already computed for its children. Uses an explicit stack rather than
+    /// recursion, so arbitrarily deep trees are safe, and visits a shared subexpression only
+    /// once however many parents it has; returns the first error `f` reports.
+    pub(crate) fn try_fold<T: Clone, E>(
+        &self,
+        mut f: impl FnMut(&AST, Vec<T>) -> Result<T, E>,
+    ) -> Result<T, E> {
+        let mut shared: HashMap<*const AST, T> = HashMap::new();
+        let mut pending = vec![(self, false, false)];
+        let mut results: Vec<T> = Vec::new();
+        while let Some((node, is_shared, children_done)) = pending.pop() {
+            if is_shared && !children_done {
+                if let Some(value) = shared.get(&(node as *const AST)) {
+                    results.push(value.clone());
+                    continue;
+                }
+            }
+            let links = node.links();
+            if children_done || links.is_empty() {
+                let values = results.split_off(results.len() - links.len());
+                let value = f(node, values)?;
+                if is_shared {
+                    shared.insert(node, value.clone());
+                }
+                results.push(value);
+            } else {
+                pending.push((node, is_shared, true));
+                pending.extend(
+                    links
+                        .into_iter()
+                        .rev()
+                        .map(|(child, is_shared)| (child, is_shared, false)),
+                );
+            }
+        }
+        return Ok(results.pop().unwrap());
+    }
+    /// [`AST::try_fold`] for folds that cannot fail.
+    pub(crate) fn fold<T: Clone>(&self, mut f: impl FnMut(&AST, Vec<T>) -> T) -> T {
+        return match self.try_fold(|node, children| Ok::<T, Infallible>(f(node, children))) {
+            Ok(value) => value,
+            Err(never) => match never {},
         };
     }
 }
-fn is_paren_required(root_op: BinOp, child_tree: &AST) -> bool {
+fn is_paren_required(root_op: BinOp, child_tree: &AST, is_left: bool) -> bool {
     return match child_tree {
         AST::Literal { .. }
         | AST::SymbolicConstant { .. }
         | AST::Variable { .. }
         | AST::Function { .. } => false,
-        AST::BinOp { op, .. } => root_op > *op,
+        // Operators of equal precedence associate to the left, except `^`.
+        AST::BinOp { op, .. } => match precedence(*op).cmp(&precedence(root_op)) {
+            Ordering::Less => true,
+            Ordering::Equal => is_left == (root_op == BinOp::Pow),
+            Ordering::Greater => false,
+        },
+    };
+}
+fn precedence(op: BinOp) -> u8 {
+    return match op {
+        BinOp::Add | BinOp::Sub => 0,
+        BinOp::Mul | BinOp::Div => 1,
+        BinOp::Pow => 2,
     };
 }
 impl fmt::Display for AST {
@@ -127,69 +274,342 @@ impl Debug for AST {
     }
 }
 
+/// Copies the top node only; operands and arguments are shared with the original.
 impl Clone for AST {
     fn clone(&self) -> Self {
-        match self {
-            Self::Literal { value } => Self::Literal {
-                value: value.clone(),
-            },
-            Self::SymbolicConstant { name } => Self::SymbolicConstant { name: name.clone() },
-            Self::Variable { name } => Self::Variable { name: name.clone() },
-            Self::Function { name, args } => Self::Function {
+        return match self {
+            AST::Function { name, args } => AST::Function {
                 name: name.clone(),
-                args: args.clone()
-            },
-            Self::BinOp { op, left, right } => Self::BinOp {
-                op: op.clone(),
-                left: left.clone(),
-                right: right.clone(),
+                args: args.clone(),
             },
+            AST::BinOp { op, left, right } => AST::binary(*op, Rc::clone(left), Rc::clone(right)),
+            _ => self.with_children(Vec::<AST>::new()),
+        };
+    }
+}
+
+/// Drops deep trees without recursing, by detaching each node's children onto a stack first.
+impl Drop for AST {
+    fn drop(&mut self) {
+        let mut stack = Vec::new();
+        self.take_children(&mut stack);
+        while let Some(mut node) = stack.pop() {
+            node.take_children(&mut stack);
         }
     }
 }
+
+impl AST {
+    fn take_children(&mut self, stack: &mut Vec<AST>) {
+        match self {
+            AST::Literal { .. } | AST::SymbolicConstant { .. } | AST::Variable { .. } => (),
+            AST::Function { args, .. } => {
+                for arg in args {
+                    take_unshared(arg, stack);
+                }
+            }
+            AST::BinOp { left, right, .. } => {
+                take_unshared(left, stack);
+                take_unshared(right, stack);
+            }
+        }
+    }
+    /// Moves the children out of this node, leaving it to be dropped. Needed because `AST`
+    /// implements `Drop`, so its fields cannot be moved out of by pattern matching.
+    pub(crate) fn into_children(mut self) -> Vec<AST> {
+        return match &mut self {
+            AST::Literal { .. } | AST::SymbolicConstant { .. } | AST::Variable { .. } => Vec::new(),
+            AST::Function { args, .. } => args.iter_mut().map(take_operand).collect(),
+            AST::BinOp { left, right, .. } => vec![take_operand(left), take_operand(right)],
+        };
+    }
+    fn has_children(&self) -> bool {
+        return matches!(self, AST::Function { .. } | AST::BinOp { .. });
+    }
+}
+/// Pushes an operand onto `stack` unless it is still referenced from elsewhere, in which case
+/// it stays where it is.
+fn take_unshared(operand: &mut Rc<AST>, stack: &mut Vec<AST>) {
+    if Rc::strong_count(operand) == 1 && operand.has_children() {
+        stack.push(take_operand(operand));
+    }
+}
+/// Moves an operand out, copying its top node if it is shared.
+fn take_operand(operand: &mut Rc<AST>) -> AST {
+    return Rc::unwrap_or_clone(mem::replace(operand, Rc::new(AST::Literal { value: 0. })));
+}
 // impl Copy for AST {}
 
-impl Add for AST {
+/// Structural equality. Literals compare by value, except that `NaN` equals itself so that
+/// `AST` can be `Eq` and used as a map key.
+impl PartialEq for AST {
+    fn eq(&self, other: &Self) -> bool {
+        let mut pending = vec![(self, other)];
+        while let Some((a, b)) = pending.pop() {
+            let same_node = match (a, b) {
+                (AST::Literal { value: a }, AST::Literal { value: b }) => {
+                    literal_bits(*a) == literal_bits(*b)
+                }
+                (AST::SymbolicConstant { name: a }, AST::SymbolicConstant { name: b })
+                | (AST::Variable { name: a }, AST::Variable { name: b }) => a == b,
+                (
+                    AST::Function {
+                        name: a,
+                        args: a_args,
+                    },
+                    AST::Function {
+                        name: b,
+                        args: b_args,
+                    },
+                ) => a == b && a_args.len() == b_args.len(),
+                (AST::BinOp { op: a, .. }, AST::BinOp { op: b, .. }) => a == b,
+                _ => false,
+            };
+            if !same_node {
+                return false;
+            }
+            pending.extend(
+                a.children()
+                    .into_iter()
+                    .zip(b.children())
+                    .filter(|(a, b)| !std::ptr::eq(*a, *b)),
+            );
+        }
+        return true;
+    }
+}
+impl Eq for AST {}
+
+impl Hash for AST {
+    /// Hashes the structure, so that equal expressions hash alike however their
+    /// subexpressions are shared. A shared subexpression is hashed once and its digest reused.
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let digest = self.fold(|node, children: Vec<u64>| {
+            let mut hasher = DefaultHasher::new();
+            mem::discriminant(node).hash(&mut hasher);
+            match node {
+                AST::Literal { value } => literal_bits(*value).hash(&mut hasher),
+                AST::SymbolicConstant { name } | AST::Variable { name } => name.hash(&mut hasher),
+                AST::Function { name, .. } => name.hash(&mut hasher),
+                AST::BinOp { op, .. } => op.hash(&mut hasher),
+            }
+            children.hash(&mut hasher);
+            return hasher.finish();
+        });
+        state.write_u64(digest);
+    }
+}
+
+/// Bit pattern of a literal with `-0.0` folded into `0.0` and all `NaN`s into one.
+pub(crate) fn literal_bits(value: f64) -> u64 {
+    if value == 0. {
+        return 0f64.to_bits();
+    }
+    if value.is_nan() {
+        return f64::NAN.to_bits();
+    }
+    return value.to_bits();
+}
+
+pub(crate) fn literal(value: f64) -> AST {
+    return AST::Literal { value };
+}
+
+pub(crate) fn call(name: &str, arg: AST) -> AST {
+    return call_with(name, &[arg]);
+}
+
+pub(crate) fn call_with(name: &str, args: &[AST]) -> AST {
+    return AST::Function {
+        name: name.to_string(),
+        args: args.iter().cloned().map(Rc::new).collect(),
+    };
+}
+
+/// `-ast`, with the sign folded into literals.
+pub(crate) fn negate(ast: AST) -> AST {
+    return match ast {
+        AST::Literal { value } => literal(-value),
+        _ => call("-", ast),
+    };
+}
+
+/// `1 / ast` written as a power: literals are folded, `a ^ -1` becomes `a` and `a ^ b` becomes
+/// `a ^ -b`, with `negate` writing the negated exponent.
+pub(crate) fn invert(ast: AST, negate: impl Fn(AST) -> AST) -> AST {
+    return match ast {
+        AST::Literal { value } if value != 0. => literal(1. / value),
+        AST::BinOp { op: BinOp::Pow, .. } => {
+            let mut operands = ast.into_children();
+            let exponent = operands.pop().unwrap();
+            let base = operands.pop().unwrap();
+            match exponent {
+                AST::Literal { value: -1. } => base,
+                AST::Literal { value } => base.pow(literal(-value)),
+                exponent => base.pow(negate(exponent)),
+            }
+        }
+        _ => ast.pow(literal(-1.)),
+    };
+}
+
+/// `value` as `numerator / denominator` in lowest terms, from its continued fraction, or
+/// `None` if no fraction with a denominator up to `max_denominator` matches it to within a few
+/// units in the last place.
+pub(crate) fn as_fraction(value: f64, max_denominator: f64) -> Option<(f64, f64)> {
+    let (mut numerator, mut previous_numerator) = (value.floor(), 1.);
+    let (mut denominator, mut previous_denominator) = (1., 0.);
+    let mut remainder = value - value.floor();
+    for _ in 0..MAX_FRACTION_STEPS {
+        if (numerator / denominator - value).abs() <= 4. * f64::EPSILON * value.abs() {
+            return Some((numerator, denominator));
+        }
+        if remainder == 0. {
+            break;
+        }
+        let reciprocal = 1. / remainder;
+        let step = reciprocal.floor();
+        remainder = reciprocal - step;
+        (numerator, previous_numerator) = (step * numerator + previous_numerator, numerator);
+        (denominator, previous_denominator) =
+            (step * denominator + previous_denominator, denominator);
+        if denominator > max_denominator {
+            break;
+        }
+    }
+    return None;
+}
+
+/// Whether `ast` is `0`, or a constant that evaluates to within [`ZERO_TOLERANCE`] of it.
+pub(crate) fn is_zero(ast: &AST, registry: &FunctionRegistry) -> bool {
+    return match ast {
+        AST::Literal { value } => *value == 0.,
+        _ if ast.variables().is_empty() => ast
+            .evaluate_with(&HashMap::new(), registry)
+            .is_ok_and(|value| value.abs() < ZERO_TOLERANCE),
+        _ => false,
+    };
+}
+
+impl<T: Into<Rc<AST>>> Add<T> for AST {
     type Output = AST;
 
-    fn add(self, rhs: Self) -> Self::Output {
-        return AST::BinOp {
-            op: BinOp::Add,
-            left: Box::new(self),
-            right: Box::new(rhs),
-        };
+    fn add(self, rhs: T) -> Self::Output {
+        return AST::binary(BinOp::Add, self, rhs);
     }
 }
-impl Sub for AST {
+impl<T: Into<Rc<AST>>> Sub<T> for AST {
     type Output = AST;
 
-    fn sub(self, rhs: Self) -> Self::Output {
-        return AST::BinOp {
-            op: BinOp::Sub,
-            left: Box::new(self),
-            right: Box::new(rhs),
-        };
+    fn sub(self, rhs: T) -> Self::Output {
+        return AST::binary(BinOp::Sub, self, rhs);
     }
 }
-impl Mul for AST {
+impl<T: Into<Rc<AST>>> Mul<T> for AST {
     type Output = AST;
 
-    fn mul(self, rhs: Self) -> Self::Output {
-        return AST::BinOp {
-            op: BinOp::Mul,
-            left: Box::new(self),
-            right: Box::new(rhs),
-        };
+    fn mul(self, rhs: T) -> Self::Output {
+        return AST::binary(BinOp::Mul, self, rhs);
     }
 }
-impl Div for AST {
+impl<T: Into<Rc<AST>>> Div<T> for AST {
     type Output = AST;
 
-    fn div(self, rhs: Self) -> Self::Output {
-        return AST::BinOp {
-            op: BinOp::Div,
-            left: Box::new(self),
-            right: Box::new(rhs),
-        };
+    fn div(self, rhs: T) -> Self::Output {
+        return AST::binary(BinOp::Div, self, rhs);
+    }
+}
+impl Neg for AST {
+    type Output = AST;
+
+    fn neg(self) -> Self::Output {
+        return negate(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{hash_map::DefaultHasher, BTreeSet},
+        hash::{Hash, Hasher},
+        rc::Rc,
+    };
+
+    use super::{as_fraction, call, call_with, invert, literal, negate, AST};
+    use crate::{parse_str, tokens::BinOp};
+
+    fn digest(ast: &AST) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        ast.hash(&mut hasher);
+        return hasher.finish();
+    }
+
+    /// `x + y` added to itself `levels` times, each sum sharing one node for both operands.
+    fn doubled(levels: usize) -> AST {
+        let mut node = Rc::new(parse_str("x + y").unwrap());
+        for _ in 0..levels {
+            node = Rc::new(AST::binary(BinOp::Add, Rc::clone(&node), Rc::clone(&node)));
+        }
+        return Rc::unwrap_or_clone(node);
+    }
+
+    #[test]
+    fn shared_subexpressions_are_walked_once() {
+        let ast = doubled(200);
+        let expected: BTreeSet<String> = ["x", "y"].iter().map(|name| name.to_string()).collect();
+        assert_eq!(ast.variables(), expected);
+        assert_eq!(digest(&ast), digest(&ast.clone()));
+    }
+
+    #[test]
+    fn function_arguments_are_shared() {
+        let mut ast = parse_str("x").unwrap();
+        for _ in 0..20_000 {
+            ast = call("sin", ast);
+        }
+        let copy = ast.clone();
+        match (&ast, &copy) {
+            (AST::Function { args: a, .. }, AST::Function { args: b, .. }) => {
+                assert!(Rc::ptr_eq(&a[0], &b[0]))
+            }
+            _ => unreachable!(),
+        }
+        let interned = call_with("f", &[ast.clone(), ast.clone()]).hash_cons();
+        assert_eq!(interned.node_count(), 20_002);
+        assert_eq!(
+            interned
+                .substitute("x", &parse_str("y").unwrap())
+                .variables()
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn hash_does_not_depend_on_sharing() {
+        let shared = doubled(2);
+        let written = parse_str("x + y + (x + y) + (x + y + (x + y))").unwrap();
+        assert_eq!(shared, written);
+        assert_eq!(digest(&shared), digest(&written));
+        assert_ne!(digest(&shared), digest(&doubled(3)));
+    }
+
+    #[test]
+    fn fractions_and_reciprocals() {
+        assert_eq!(
+            as_fraction(1. / 1307674368000., 1e13),
+            Some((1., 1307674368000.))
+        );
+        assert_eq!(as_fraction(0.375, 100.), Some((3., 8.)));
+        assert_eq!(as_fraction(-2. / 3., 100.), Some((-2., 3.)));
+        assert_eq!(as_fraction(1. / 101., 100.), None);
+        assert_eq!(as_fraction(std::f64::consts::PI, 1e13), None);
+        let inverse = |source: &str| invert(parse_str(source).unwrap(), negate).to_string();
+        assert_eq!(inverse("4"), "0.25");
+        assert_eq!(inverse("x ^ 2"), "x ^ -2");
+        assert_eq!(inverse("x ^ y"), "x ^ -(y)");
+        assert_eq!(inverse("sin(x)"), "sin(x) ^ -1");
+        let x = parse_str("x").unwrap();
+        assert_eq!(invert(x.clone().pow(literal(-1.)), negate), x);
     }
 }
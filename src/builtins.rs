@@ -0,0 +1,405 @@
+use crate::{
+    ast::{call, call_with, literal, AST},
+    functions::{FunctionDefinition, FunctionRegistry},
+    simplifier::EvaluationError,
+};
+
+/// A point at which a function has a known exact value.
+enum Point {
+    Literal(f64),
+    Constant(&'static str),
+}
+
+use Point::{Constant, Literal};
+
+pub(crate) fn register_builtins(registry: &mut FunctionRegistry) {
+    registry.register("-", unary(|x| -x, |_| literal(-1.)));
+    registry.register(
+        "sin",
+        unary(f64::sin, |x| call("cos", x))
+            .special_values(at(&[(Literal(0.), 0.), (Constant("pi"), 0.)]))
+            .antiderivative(|u| call("-", call("cos", u.clone()))),
+    );
+    registry.register(
+        "cos",
+        unary(f64::cos, |x| literal(-1.) * call("sin", x))
+            .special_values(at(&[(Literal(0.), 1.), (Constant("pi"), -1.)]))
+            .antiderivative(|u| call("sin", u.clone())),
+    );
+    registry.register(
+        "tan",
+        unary(f64::tan, |x| call("sec", x).pow(literal(2.)))
+            .special_values(at(&[(Literal(0.), 0.), (Constant("pi"), 0.)]))
+            .antiderivative(|u| call("-", call("ln", call("abs", call("cos", u.clone()))))),
+    );
+    registry.register(
+        "sec",
+        reciprocal(f64::cos, |x| call("sec", x.clone()) * call("tan", x))
+            .special_values(at(&[(Literal(0.), 1.), (Constant("pi"), -1.)]))
+            .antiderivative(|u| {
+                call(
+                    "ln",
+                    call("abs", call("sec", u.clone()) + call("tan", u.clone())),
+                )
+            }),
+    );
+    registry.register(
+        "csc",
+        reciprocal(f64::sin, |x| {
+            call("-", call("csc", x.clone()) * call("cot", x))
+        })
+        .antiderivative(|u| {
+            call(
+                "-",
+                call(
+                    "ln",
+                    call("abs", call("csc", u.clone()) + call("cot", u.clone())),
+                ),
+            )
+        }),
+    );
+    registry.register(
+        "cot",
+        reciprocal(f64::tan, |x| call("-", call("csc", x).pow(literal(2.))))
+            .antiderivative(|u| call("ln", call("abs", call("sin", u.clone())))),
+    );
+    registry.register(
+        "asin",
+        restricted(
+            "asin",
+            |x| x.abs() <= 1.,
+            f64::asin,
+            |x| literal(1.) / call("sqrt", literal(1.) - x.pow(literal(2.))),
+        )
+        .special_values(at(&[(Literal(0.), 0.)]))
+        .antiderivative(|u| {
+            u.clone() * call("asin", u.clone())
+                + call("sqrt", literal(1.) - u.clone().pow(literal(2.)))
+        }),
+    );
+    registry.register(
+        "acos",
+        restricted(
+            "acos",
+            |x| x.abs() <= 1.,
+            f64::acos,
+            |x| {
+                call(
+                    "-",
+                    literal(1.) / call("sqrt", literal(1.) - x.pow(literal(2.))),
+                )
+            },
+        )
+        .special_values(at(&[(Literal(1.), 0.)]))
+        .antiderivative(|u| {
+            u.clone() * call("acos", u.clone())
+                - call("sqrt", literal(1.) - u.clone().pow(literal(2.)))
+        }),
+    );
+    registry.register(
+        "atan",
+        unary(f64::atan, |x| {
+            literal(1.) / (literal(1.) + x.pow(literal(2.)))
+        })
+        .special_values(at(&[(Literal(0.), 0.)]))
+        .antiderivative(|u| {
+            u.clone() * call("atan", u.clone())
+                - call("ln", literal(1.) + u.clone().pow(literal(2.))) / literal(2.)
+        }),
+    );
+    registry.register(
+        "sinh",
+        unary(f64::sinh, |x| call("cosh", x))
+            .special_values(at(&[(Literal(0.), 0.)]))
+            .antiderivative(|u| call("cosh", u.clone())),
+    );
+    registry.register(
+        "cosh",
+        unary(f64::cosh, |x| call("sinh", x))
+            .special_values(at(&[(Literal(0.), 1.)]))
+            .antiderivative(|u| call("sinh", u.clone())),
+    );
+    registry.register(
+        "tanh",
+        unary(f64::tanh, |x| {
+            literal(1.) / call("cosh", x).pow(literal(2.))
+        })
+        .special_values(at(&[(Literal(0.), 0.)]))
+        .antiderivative(|u| call("ln", call("cosh", u.clone()))),
+    );
+    registry.register(
+        "asinh",
+        unary(f64::asinh, |x| {
+            literal(1.) / call("sqrt", x.pow(literal(2.)) + literal(1.))
+        })
+        .special_values(at(&[(Literal(0.), 0.)])),
+    );
+    registry.register(
+        "acosh",
+        restricted(
+            "acosh",
+            |x| x >= 1.,
+            f64::acosh,
+            |x| literal(1.) / call("sqrt", x.pow(literal(2.)) - literal(1.)),
+        )
+        .special_values(at(&[(Literal(1.), 0.)])),
+    );
+    registry.register(
+        "atanh",
+        restricted(
+            "atanh",
+            |x| x.abs() < 1.,
+            f64::atanh,
+            |x| literal(1.) / (literal(1.) - x.pow(literal(2.))),
+        )
+        .special_values(at(&[(Literal(0.), 0.)])),
+    );
+    registry.register(
+        "exp",
+        unary(f64::exp, |x| call("exp", x))
+            .special_values(at(&[(Literal(0.), 1.)]))
+            .antiderivative(|u| call("exp", u.clone())),
+    );
+    registry.register(
+        "ln",
+        restricted("ln", |x| x > 0., f64::ln, |x| literal(1.) / x)
+            .special_values(at(&[(Literal(1.), 0.), (Constant("e"), 1.)]))
+            .antiderivative(|u| u.clone() * call("ln", u.clone()) - u.clone()),
+    );
+    registry.register(
+        "log10",
+        restricted(
+            "log10",
+            |x| x > 0.,
+            f64::log10,
+            |x| literal(1.) / (x * call("ln", literal(10.))),
+        )
+        .special_values(at(&[(Literal(1.), 0.), (Literal(10.), 1.)]))
+        .antiderivative(|u| {
+            (u.clone() * call("ln", u.clone()) - u.clone()) / call("ln", literal(10.))
+        }),
+    );
+    registry.register(
+        "log2",
+        restricted(
+            "log2",
+            |x| x > 0.,
+            f64::log2,
+            |x| literal(1.) / (x * call("ln", literal(2.))),
+        )
+        .special_values(at(&[(Literal(1.), 0.), (Literal(2.), 1.)]))
+        .antiderivative(|u| {
+            (u.clone() * call("ln", u.clone()) - u.clone()) / call("ln", literal(2.))
+        }),
+    );
+    registry.register(
+        "sqrt",
+        restricted(
+            "sqrt",
+            |x| x >= 0.,
+            f64::sqrt,
+            |x| literal(1.) / (literal(2.) * call("sqrt", x)),
+        )
+        .special_values(at(&[(Literal(0.), 0.), (Literal(1.), 1.)]))
+        .antiderivative(|u| literal(2.) * u.clone().pow(literal(1.5)) / literal(3.)),
+    );
+    registry.register(
+        "abs",
+        unary(f64::abs, |x| call("sign", x))
+            .special_values(|args| match args {
+                [AST::Literal { value }] => Some(literal(value.abs())),
+                _ => None,
+            })
+            .antiderivative(|u| u.clone() * call("abs", u.clone()) / literal(2.)),
+    );
+    registry.register(
+        "sign",
+        unary(sign, |_| literal(0.))
+            .special_values(|args| match args {
+                [AST::Literal { value }] => Some(literal(sign(*value))),
+                _ => None,
+            })
+            .antiderivative(|u| call("abs", u.clone())),
+    );
+    registry.register("step", unary(|x| (1. + sign(x)) / 2., |_| literal(0.)));
+
+    registry.register(
+        "atan2",
+        binary(|y, x| Ok(y.atan2(x)))
+            .derivative(|args| args[1].clone() / squared_norm(args))
+            .derivative(|args| call("-", args[0].clone()) / squared_norm(args)),
+    );
+    registry.register(
+        "log",
+        binary(|base, x| {
+            if base <= 0. || base == 1. {
+                return Err(domain_error("log", base));
+            }
+            if x <= 0. {
+                return Err(domain_error("log", x));
+            }
+            return Ok(x.log(base));
+        })
+        .derivative(|args| {
+            call("-", call("ln", args[1].clone()))
+                / (args[0].clone() * call("ln", args[0].clone()).pow(literal(2.)))
+        })
+        .derivative(|args| literal(1.) / (args[1].clone() * call("ln", args[0].clone()))),
+    );
+    registry.register(
+        "pow",
+        binary(|base, exponent| {
+            if base < 0. && exponent.fract() != 0. {
+                return Err(domain_error("pow", base));
+            }
+            if base == 0. && exponent < 0. {
+                return Err(EvaluationError::ZeroDivisionError);
+            }
+            return Ok(base.powf(exponent));
+        })
+        .derivative(|args| args[1].clone() * args[0].clone().pow(args[1].clone() - literal(1.)))
+        .derivative(|args| args[0].clone().pow(args[1].clone()) * call("ln", args[0].clone())),
+    );
+    registry.register(
+        "hypot",
+        binary(|a, b| Ok(a.hypot(b)))
+            .derivative(|args| args[0].clone() / call_with("hypot", args))
+            .derivative(|args| args[1].clone() / call_with("hypot", args)),
+    );
+    registry.register(
+        "min",
+        binary(|a, b| Ok(a.min(b)))
+            .derivative(|args| call("step", args[1].clone() - args[0].clone()))
+            .derivative(|args| call("step", args[0].clone() - args[1].clone())),
+    );
+    registry.register(
+        "max",
+        binary(|a, b| Ok(a.max(b)))
+            .derivative(|args| call("step", args[0].clone() - args[1].clone()))
+            .derivative(|args| call("step", args[1].clone() - args[0].clone())),
+    );
+}
+
+fn unary(evaluate: fn(f64) -> f64, derivative: fn(AST) -> AST) -> FunctionDefinition {
+    return FunctionDefinition::unary(move |x| Ok(evaluate(x)))
+        .derivative(move |args| derivative(args[0].clone()));
+}
+
+/// A unary function that is only defined where `domain` holds.
+fn restricted(
+    name: &'static str,
+    domain: fn(f64) -> bool,
+    evaluate: fn(f64) -> f64,
+    derivative: fn(AST) -> AST,
+) -> FunctionDefinition {
+    return FunctionDefinition::unary(move |x| {
+        if domain(x) {
+            Ok(evaluate(x))
+        } else {
+            Err(domain_error(name, x))
+        }
+    })
+    .derivative(move |args| derivative(args[0].clone()));
+}
+
+/// A unary function computed as `1 / denominator(x)`.
+fn reciprocal(denominator: fn(f64) -> f64, derivative: fn(AST) -> AST) -> FunctionDefinition {
+    return FunctionDefinition::unary(move |x| {
+        let value = denominator(x);
+        if value == 0. {
+            Err(EvaluationError::ZeroDivisionError)
+        } else {
+            Ok(1. / value)
+        }
+    })
+    .derivative(move |args| derivative(args[0].clone()));
+}
+
+fn binary<F>(evaluate: F) -> FunctionDefinition
+where
+    F: Fn(f64, f64) -> Result<f64, EvaluationError> + Send + Sync + 'static,
+{
+    return FunctionDefinition::new(2, move |args| evaluate(args[0], args[1]));
+}
+
+/// Special values of a unary function, given as `(point, value)` pairs.
+fn at(points: &'static [(Point, f64)]) -> impl Fn(&[AST]) -> Option<AST> {
+    return move |args| {
+        points.iter().find_map(|(point, value)| {
+            let matches = match (point, &args[0]) {
+                (Literal(expected), AST::Literal { value }) => value == expected,
+                (Constant(expected), AST::SymbolicConstant { name }) => name == expected,
+                _ => false,
+            };
+            if matches {
+                Some(literal(*value))
+            } else {
+                None
+            }
+        })
+    };
+}
+
+fn squared_norm(args: &[AST]) -> AST {
+    return args[0].clone().pow(literal(2.)) + args[1].clone().pow(literal(2.));
+}
+
+fn sign(x: f64) -> f64 {
+    return if x == 0. { 0. } else { x.signum() };
+}
+
+fn domain_error(function: &str, argument: f64) -> EvaluationError {
+    return EvaluationError::DomainError {
+        function: function.to_string(),
+        argument,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{differentiate_str, parse_str, simplifier::EvaluationError};
+
+    fn derivative(source: &str) -> String {
+        return differentiate_str(source).unwrap().to_string();
+    }
+
+    fn value(source: &str) -> f64 {
+        let env = HashMap::from([("x".to_string(), 2.)]);
+        return parse_str(source).unwrap().evaluate(&env).unwrap();
+    }
+
+    #[test]
+    fn derivatives() {
+        assert_eq!(derivative("sin(x)"), "cos(x)");
+        assert_eq!(derivative("tan(x)"), "sec(x) ^ 2");
+        assert_eq!(derivative("sec(x)"), "sec(x) * tan(x)");
+        assert_eq!(derivative("asin(x)"), "1 / sqrt(1 - x ^ 2)");
+        assert_eq!(derivative("atan(x)"), "1 / (x ^ 2 + 1)");
+        assert_eq!(derivative("sinh(x)"), "cosh(x)");
+        assert_eq!(derivative("ln(x)"), "1 / x");
+        assert_eq!(derivative("abs(x)"), "sign(x)");
+        assert_eq!(derivative("sqrt(x)"), "1 / (2 * sqrt(x))");
+        assert_eq!(derivative("log10(x)"), "1 / (x * ln(10))");
+        assert_eq!(derivative("log(2, x)"), "1 / (x * ln(2))");
+    }
+
+    #[test]
+    fn values() {
+        assert_eq!(value("hypot(3, 4)"), 5.);
+        assert_eq!(value("atan2(1, 1)"), std::f64::consts::FRAC_PI_4);
+        assert_eq!(value("log(2, 8)"), 3.);
+        assert_eq!(value("min(3, x)"), 2.);
+        assert_eq!(value("max(3, x)"), 3.);
+        assert_eq!(value("sign(-2)"), -1.);
+        assert_eq!(value("step(0)"), 0.5);
+        assert_eq!(value("sec(0)"), 1.);
+        assert_eq!(value("sec(x)"), 1. / 2f64.cos());
+        assert!(matches!(
+            parse_str("csc(0)").unwrap().evaluate(&HashMap::new()),
+            Err(EvaluationError::ZeroDivisionError)
+        ));
+        assert_eq!(value("acosh(1)"), 0.);
+    }
+}
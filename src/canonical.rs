@@ -0,0 +1,247 @@
+use std::cmp::Ordering;
+
+use crate::{
+    ast::{invert, literal, AST},
+    tokens::BinOp,
+};
+
+impl AST {
+    /// Rewrites the expression into a canonical form, so that expressions which differ only in
+    /// the order of commutative operands or in how subtraction and division are written compare
+    /// equal. `a - b` becomes `a + -1 * b` and `a / b` becomes `a * b ^ -1`; `+` and `*` chains
+    /// are flattened, their numeric operands folded into one, and the rest sorted.
+    pub fn canonicalize(&self) -> AST {
+        let canonical = self.fold(|node, children: Vec<Part>| match node {
+            AST::Literal { value } => Part::Done(AST::Literal {
+                value: if *value == 0. { 0. } else { *value },
+            }),
+            AST::SymbolicConstant { .. } | AST::Variable { .. } => Part::Done(node.clone()),
+            AST::Function { name, args } if name == "-" && args.len() == 1 => {
+                match children.into_iter().next().unwrap() {
+                    Part::Sum(terms) => {
+                        Part::Sum(terms.into_iter().map(scale_by_minus_one).collect())
+                    }
+                    Part::Product(mut factors) => {
+                        factors.push(AST::Literal { value: -1. });
+                        Part::Product(factors)
+                    }
+                    Part::Done(ast) => Part::Done(scale_by_minus_one(ast)),
+                }
+            }
+            AST::Function { .. } => {
+                Part::Done(node.with_children(children.into_iter().map(Part::into_ast).collect()))
+            }
+            AST::BinOp { op, .. } => {
+                let mut children = children.into_iter();
+                let left = children.next().unwrap();
+                let right = children.next().unwrap();
+                match op {
+                    BinOp::Add => Part::Sum(left.terms(right.terms(Vec::new()))),
+                    BinOp::Sub => {
+                        let negated = right
+                            .terms(Vec::new())
+                            .into_iter()
+                            .map(scale_by_minus_one)
+                            .collect();
+                        Part::Sum(left.terms(negated))
+                    }
+                    BinOp::Mul => Part::Product(left.factors(right.factors(Vec::new()))),
+                    BinOp::Div => {
+                        let inverted = right
+                            .factors(Vec::new())
+                            .into_iter()
+                            .map(|factor| invert(factor, scale_by_minus_one))
+                            .collect();
+                        Part::Product(left.factors(inverted))
+                    }
+                    BinOp::Pow => Part::Done(left.into_ast().pow(right.into_ast())),
+                }
+            }
+        });
+        return canonical.into_ast();
+    }
+}
+
+/// The canonical form of a subexpression, with `+` and `*` chains kept as lists of canonical
+/// operands until a node outside the chain needs them built.
+#[derive(Clone)]
+enum Part {
+    Sum(Vec<AST>),
+    Product(Vec<AST>),
+    Done(AST),
+}
+
+impl Part {
+    fn into_ast(self) -> AST {
+        return match self {
+            Part::Sum(terms) => build_sum(terms),
+            Part::Product(factors) => build_product(factors),
+            Part::Done(ast) => ast,
+        };
+    }
+    /// The terms of this part as a sum, followed by `rest`.
+    fn terms(self, mut rest: Vec<AST>) -> Vec<AST> {
+        let mut terms = match self {
+            Part::Sum(terms) => terms,
+            part => vec![part.into_ast()],
+        };
+        terms.append(&mut rest);
+        return terms;
+    }
+    /// The factors of this part as a product, followed by `rest`.
+    fn factors(self, mut rest: Vec<AST>) -> Vec<AST> {
+        let mut factors = match self {
+            Part::Product(factors) => factors,
+            part => vec![part.into_ast()],
+        };
+        factors.append(&mut rest);
+        return factors;
+    }
+}
+
+/// `-ast` in canonical form, i.e. `-1 * ast`.
+fn scale_by_minus_one(ast: AST) -> AST {
+    return build_product(vec![literal(-1.), ast]);
+}
+
+/// Builds a sorted `+` chain from canonical terms, folding the numeric ones.
+fn build_sum(terms: Vec<AST>) -> AST {
+    let mut constant = 0.;
+    let mut rest = Vec::new();
+    let mut pending = terms;
+    while let Some(term) = pending.pop() {
+        match term {
+            AST::Literal { value } => constant += value,
+            AST::BinOp { op: BinOp::Add, .. } => pending.extend(term.into_children()),
+            _ => rest.push(term),
+        }
+    }
+    if constant != 0. || rest.is_empty() {
+        rest.push(AST::Literal { value: constant });
+    }
+    rest.sort_by(compare);
+    return rest.into_iter().reduce(|sum, term| sum + term).unwrap();
+}
+
+/// Builds a sorted `*` chain from canonical factors, folding the numeric ones into a leading
+/// coefficient.
+fn build_product(factors: Vec<AST>) -> AST {
+    let mut coefficient = 1.;
+    let mut rest = Vec::new();
+    let mut pending = factors;
+    while let Some(factor) = pending.pop() {
+        match factor {
+            AST::Literal { value } => coefficient *= value,
+            AST::BinOp { op: BinOp::Mul, .. } => pending.extend(factor.into_children()),
+            _ => rest.push(factor),
+        }
+    }
+    if coefficient == 0. || rest.is_empty() {
+        return AST::Literal {
+            value: if coefficient == 0. { 0. } else { coefficient },
+        };
+    }
+    rest.sort_by(compare);
+    if coefficient != 1. {
+        rest.insert(0, AST::Literal { value: coefficient });
+    }
+    return rest
+        .into_iter()
+        .reduce(|product, factor| product * factor)
+        .unwrap();
+}
+
+/// A total order on expressions: literals, then constants, variables, functions and operators,
+/// each compared by their contents.
+fn compare(a: &AST, b: &AST) -> Ordering {
+    let mut pending = vec![Step::Compare(a, b)];
+    while let Some(step) = pending.pop() {
+        let (a, b) = match step {
+            Step::Compare(a, b) => (a, b),
+            Step::Then(Ordering::Equal) => continue,
+            Step::Then(ordering) => return ordering,
+        };
+        if std::ptr::eq(a, b) {
+            continue;
+        }
+        let ordering = match (a, b) {
+            (AST::Literal { value: a }, AST::Literal { value: b }) => a.total_cmp(b),
+            (AST::SymbolicConstant { name: a }, AST::SymbolicConstant { name: b })
+            | (AST::Variable { name: a }, AST::Variable { name: b }) => a.cmp(b),
+            (
+                AST::Function {
+                    name: a,
+                    args: a_args,
+                },
+                AST::Function {
+                    name: b,
+                    args: b_args,
+                },
+            ) => {
+                pending.push(Step::Then(a_args.len().cmp(&b_args.len())));
+                a.cmp(b)
+            }
+            (AST::BinOp { op: a, .. }, AST::BinOp { op: b, .. }) => a.cmp(b),
+            _ => rank(a).cmp(&rank(b)),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+        let children: Vec<_> = a.children().into_iter().zip(b.children()).collect();
+        pending.extend(children.into_iter().rev().map(|(a, b)| Step::Compare(a, b)));
+    }
+    return Ordering::Equal;
+}
+
+/// Work left for [`compare`]: a pair of subexpressions, or the ordering to report if
+/// everything compared before it was equal.
+enum Step<'a> {
+    Compare(&'a AST, &'a AST),
+    Then(Ordering),
+}
+
+fn rank(ast: &AST) -> u8 {
+    return match ast {
+        AST::Literal { .. } => 0,
+        AST::SymbolicConstant { .. } => 1,
+        AST::Variable { .. } => 2,
+        AST::Function { .. } => 3,
+        AST::BinOp { .. } => 4,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ast::call, parse_str, AST};
+
+    fn canonical(source: &str) -> String {
+        return parse_str(source).unwrap().canonicalize().to_string();
+    }
+
+    #[test]
+    fn equivalent_forms_agree() {
+        assert_eq!(canonical("b + a - c"), "a + b + -1 * c");
+        assert_eq!(canonical("a - (b + c)"), canonical("a - b - c"));
+        assert_eq!(canonical("x / (y / z)"), canonical("x * z / y"));
+        assert_eq!(canonical("sin(y + x) * 2"), canonical("2 * sin(x + y)"));
+        assert_eq!(canonical("y * x / 2"), "0.5 * x * y");
+    }
+
+    #[test]
+    fn deep_expressions_do_not_overflow() {
+        let mut ast = AST::Variable {
+            name: "x".to_string(),
+        };
+        for _ in 0..100_000 {
+            ast = call(
+                "sin",
+                ast.pow(AST::Variable {
+                    name: "y".to_string(),
+                }),
+            );
+        }
+        let canonical = ast.canonicalize();
+        assert_eq!(canonical, ast);
+        assert_eq!(canonical.canonicalize(), canonical);
+    }
+}
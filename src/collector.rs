@@ -0,0 +1,375 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+};
+
+use crate::{
+    ast::{as_fraction, call, AST},
+    functions::FunctionRegistry,
+    simplifier::EvaluationError,
+    tokens::BinOp,
+};
+
+/// Largest denominator a collected coefficient is written over, so that `x / 3` is not turned
+/// into `0.333 * x` while coefficients that only happen to be close to a fraction stay as they
+/// are.
+const MAX_DENOMINATOR: f64 = 100.;
+
+/// Flattens the `+`/`-` or `*`/`/` chain rooted at `ast`, folds its numeric coefficients,
+/// collects like terms (`2x + 3x -> 5x`) and combines powers of the same base
+/// (`x*x^2 -> x^3`). Returns `None` for any other node.
+pub(crate) fn collect(
+    ast: &AST,
+    registry: &FunctionRegistry,
+) -> Result<Option<AST>, EvaluationError> {
+    return match ast {
+        AST::BinOp {
+            op: BinOp::Add | BinOp::Sub,
+            ..
+        } => Ok(Some(Sum::from_ast(ast, registry)?.into_ast())),
+        AST::BinOp {
+            op: BinOp::Mul | BinOp::Div,
+            ..
+        } => Ok(Some(Product::from_ast(ast, registry)?.into_ast())),
+        _ => Ok(None),
+    };
+}
+
+/// A sum of like-term groups, each a coefficient times a product without a coefficient.
+/// The `Product` of the constant group has no factors.
+struct Sum {
+    terms: Vec<(f64, Product)>,
+    /// Positions in `terms` by [`Product::factors_key`], so like terms are found without
+    /// scanning every group.
+    index: HashMap<u64, Vec<usize>>,
+}
+
+impl Sum {
+    fn from_ast(ast: &AST, registry: &FunctionRegistry) -> Result<Sum, EvaluationError> {
+        let mut sum = Sum {
+            terms: Vec::new(),
+            index: HashMap::new(),
+        };
+        sum.add(ast, 1., registry)?;
+        return Ok(sum);
+    }
+    fn add(
+        &mut self,
+        ast: &AST,
+        sign: f64,
+        registry: &FunctionRegistry,
+    ) -> Result<(), EvaluationError> {
+        // Walk the chain with a worklist rather than recursion so long sums are safe.
+        let mut pending = vec![(ast, sign)];
+        while let Some((ast, sign)) = pending.pop() {
+            match ast {
+                AST::BinOp {
+                    op: BinOp::Add,
+                    left,
+                    right,
+                } => {
+                    pending.push((right, sign));
+                    pending.push((left, sign));
+                }
+                AST::BinOp {
+                    op: BinOp::Sub,
+                    left,
+                    right,
+                } => {
+                    pending.push((right, -sign));
+                    pending.push((left, sign));
+                }
+                AST::Function { name, args } if name == "-" && args.len() == 1 => {
+                    pending.push((&args[0], -sign));
+                }
+                _ => {
+                    let mut term = Product::from_ast(ast, registry)?;
+                    let coefficient = sign * term.coefficient;
+                    term.coefficient = 1.;
+                    let candidates = self.index.entry(term.factors_key()).or_default();
+                    let terms = &mut self.terms;
+                    match candidates
+                        .iter()
+                        .find(|index| terms[**index].1.has_same_factors(&term))
+                    {
+                        Some(index) => terms[*index].0 += coefficient,
+                        None => {
+                            candidates.push(terms.len());
+                            terms.push((coefficient, term));
+                        }
+                    }
+                }
+            }
+        }
+        return Ok(());
+    }
+    fn into_ast(mut self) -> AST {
+        self.terms.retain(|(coefficient, _)| *coefficient != 0.);
+        // Keep the constant term last, e.g. `x + 1` rather than `1 + x`.
+        if let Some(index) = self
+            .terms
+            .iter()
+            .position(|(_, term)| term.factors.is_empty())
+        {
+            let constant = self.terms.remove(index);
+            self.terms.push(constant);
+        }
+        // Prefer `1 - x` over `-(x) + 1`.
+        if let Some(index) = self
+            .terms
+            .iter()
+            .position(|(coefficient, _)| *coefficient > 0.)
+        {
+            if self.terms[0].0 < 0. {
+                let positive = self.terms.remove(index);
+                self.terms.insert(0, positive);
+            }
+        }
+        let mut terms = self.terms.into_iter();
+        let mut sum = match terms.next() {
+            Some((coefficient, term)) => term.with_coefficient(coefficient).into_ast(),
+            None => return AST::Literal { value: 0. },
+        };
+        for (coefficient, term) in terms {
+            sum = if coefficient < 0. {
+                sum - term.with_coefficient(-coefficient).into_ast()
+            } else {
+                sum + term.with_coefficient(coefficient).into_ast()
+            };
+        }
+        return sum;
+    }
+}
+
+/// A numeric coefficient times a list of `(base, exponent)` factors with distinct bases.
+struct Product {
+    coefficient: f64,
+    factors: Vec<(AST, AST)>,
+    /// Positions in `factors` by base.
+    index: HashMap<AST, usize>,
+}
+
+impl Product {
+    fn from_ast(ast: &AST, registry: &FunctionRegistry) -> Result<Product, EvaluationError> {
+        let mut product = Product {
+            coefficient: 1.,
+            factors: Vec::new(),
+            index: HashMap::new(),
+        };
+        product.multiply(ast, false, registry)?;
+        return Ok(product);
+    }
+    fn multiply(
+        &mut self,
+        ast: &AST,
+        invert: bool,
+        registry: &FunctionRegistry,
+    ) -> Result<(), EvaluationError> {
+        let mut pending = vec![(ast, invert)];
+        while let Some((ast, invert)) = pending.pop() {
+            match ast {
+                AST::BinOp {
+                    op: BinOp::Mul,
+                    left,
+                    right,
+                } => {
+                    pending.push((right, invert));
+                    pending.push((left, invert));
+                }
+                AST::BinOp {
+                    op: BinOp::Div,
+                    left,
+                    right,
+                } => {
+                    pending.push((right, !invert));
+                    pending.push((left, invert));
+                }
+                AST::Function { name, args } if name == "-" && args.len() == 1 => {
+                    self.coefficient = -self.coefficient;
+                    pending.push((&args[0], invert));
+                }
+                AST::Literal { value } if invert => {
+                    if *value == 0. {
+                        return Err(EvaluationError::ZeroDivisionError);
+                    }
+                    self.coefficient /= value;
+                }
+                AST::Literal { value } => self.coefficient *= value,
+                AST::BinOp {
+                    op: BinOp::Pow,
+                    left,
+                    right,
+                } => {
+                    self.add_factor(
+                        (**left).clone(),
+                        negate_if((**right).clone(), invert),
+                        registry,
+                    )?;
+                }
+                _ => self.add_factor(
+                    ast.clone(),
+                    negate_if(AST::Literal { value: 1. }, invert),
+                    registry,
+                )?,
+            }
+        }
+        return Ok(());
+    }
+    fn add_factor(
+        &mut self,
+        base: AST,
+        exponent: AST,
+        registry: &FunctionRegistry,
+    ) -> Result<(), EvaluationError> {
+        match self.index.get(&base) {
+            Some(index) => {
+                let sum = &mut self.factors[*index].1;
+                *sum = match (&*sum, &exponent) {
+                    (AST::Literal { value: a }, AST::Literal { value: b }) => {
+                        AST::Literal { value: a + b }
+                    }
+                    _ => {
+                        let mut sum = sum.clone() + exponent;
+                        sum.simplify_with(registry)?;
+                        sum
+                    }
+                };
+            }
+            None => {
+                self.index.insert(base.clone(), self.factors.len());
+                self.factors.push((base, exponent));
+            }
+        }
+        return Ok(());
+    }
+    /// A hash of the factors that does not depend on their order, so that products with the
+    /// same factors get the same key.
+    fn factors_key(&self) -> u64 {
+        return self
+            .factors
+            .iter()
+            .map(|factor| {
+                let mut hasher = DefaultHasher::new();
+                factor.hash(&mut hasher);
+                hasher.finish()
+            })
+            .fold(0, u64::wrapping_add);
+    }
+    /// Whether both products have the same factors, in any order.
+    fn has_same_factors(&self, other: &Product) -> bool {
+        return self.factors.len() == other.factors.len()
+            && self
+                .factors
+                .iter()
+                .all(|factor| other.factors.contains(factor));
+    }
+    fn with_coefficient(mut self, coefficient: f64) -> Product {
+        self.coefficient = coefficient;
+        return self;
+    }
+    /// Rebuilds the product as `coefficient * numerator / denominator`, moving factors with
+    /// negative exponents into the denominator.
+    fn into_ast(self) -> AST {
+        if self.coefficient == 0. {
+            return AST::Literal { value: 0. };
+        }
+        let mut numerator = Vec::new();
+        let mut denominator = Vec::new();
+        for (base, exponent) in self.factors {
+            match exponent {
+                AST::Literal { value: 0. } => (),
+                AST::Literal { value: 1. } => numerator.push(base),
+                AST::Literal { value: -1. } => denominator.push(base),
+                AST::Literal { value } if value < 0. => {
+                    denominator.push(base.pow(AST::Literal { value: -value }))
+                }
+                AST::Function { ref name, ref args } if name == "-" && args.len() == 1 => {
+                    denominator.push(base.pow(args[0].clone()))
+                }
+                _ => numerator.push(base.pow(exponent)),
+            }
+        }
+        if numerator.is_empty() && denominator.is_empty() {
+            return AST::Literal {
+                value: self.coefficient,
+            };
+        }
+        let (numerator_coefficient, denominator_coefficient) =
+            as_fraction(self.coefficient, MAX_DENOMINATOR).unwrap_or((self.coefficient, 1.));
+        // -(x * y) reads better than -1 * x * y.
+        let negate = numerator_coefficient == -1. && !numerator.is_empty();
+        if !negate && (numerator_coefficient != 1. || numerator.is_empty()) {
+            numerator.insert(
+                0,
+                AST::Literal {
+                    value: numerator_coefficient,
+                },
+            );
+        }
+        if denominator_coefficient != 1. {
+            denominator.insert(
+                0,
+                AST::Literal {
+                    value: denominator_coefficient,
+                },
+            );
+        }
+        let numerator = numerator
+            .into_iter()
+            .reduce(|product, factor| product * factor)
+            .unwrap();
+        let numerator = if negate {
+            call("-", numerator)
+        } else {
+            numerator
+        };
+        return match denominator
+            .into_iter()
+            .reduce(|product, factor| product * factor)
+        {
+            Some(denominator) => numerator / denominator,
+            None => numerator,
+        };
+    }
+}
+
+fn negate_if(exponent: AST, negate: bool) -> AST {
+    if !negate {
+        return exponent;
+    }
+    return match &exponent {
+        AST::Literal { value } => AST::Literal { value: -value },
+        AST::Function { name, args } if name == "-" && args.len() == 1 => {
+            exponent.into_children().remove(0)
+        }
+        _ => call("-", exponent),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse_str;
+
+    fn simplified(source: &str) -> String {
+        let mut ast = parse_str(source).unwrap();
+        ast.simplify().unwrap();
+        return ast.to_string();
+    }
+
+    #[test]
+    fn collects_like_terms_whatever_the_factor_order() {
+        assert_eq!(simplified("x*y + 2*y*x - x*y"), "2 * x * y");
+        assert_eq!(simplified("a*b*c + c*b*a + b*a*c"), "3 * a * b * c");
+        assert_eq!(simplified("x * y / x * y"), "y ^ 2");
+    }
+
+    #[test]
+    fn collects_long_sums() {
+        let terms: Vec<String> = (1..=200).map(|i| format!("{}*x^{}", i, i % 10)).collect();
+        assert_eq!(
+            simplified(&terms.join(" + ")),
+            "1920 * x + 1940 * x ^ 2 + 1960 * x ^ 3 + 1980 * x ^ 4 + 2000 * x ^ 5 \
+             + 2020 * x ^ 6 + 2040 * x ^ 7 + 2060 * x ^ 8 + 2080 * x ^ 9 + 2100"
+        );
+    }
+}
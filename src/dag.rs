@@ -0,0 +1,92 @@
+use std::{
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
+
+use crate::{
+    ast::{literal_bits, AST},
+    tokens::BinOp,
+};
+
+/// Hands out a single shared node for every distinct expression, so that structurally equal
+/// subexpressions become the same `Rc` (hash-consing). Nodes must be interned bottom-up: a
+/// node is looked up by its own kind and the addresses of its already-interned operands.
+#[derive(Default)]
+pub(crate) struct Interner {
+    nodes: HashMap<Shape, Rc<AST>>,
+}
+
+/// What identifies a node once its operands are interned.
+#[derive(PartialEq, Eq, Hash)]
+enum Shape {
+    Literal(u64),
+    SymbolicConstant(String),
+    Variable(String),
+    Function(String, Vec<*const AST>),
+    BinOp(BinOp, *const AST, *const AST),
+}
+
+impl Interner {
+    pub(crate) fn intern(&mut self, node: AST) -> Rc<AST> {
+        return Rc::clone(
+            self.nodes
+                .entry(shape(&node))
+                .or_insert_with(|| Rc::new(node)),
+        );
+    }
+}
+
+fn shape(node: &AST) -> Shape {
+    return match node {
+        AST::Literal { value } => Shape::Literal(literal_bits(*value)),
+        AST::SymbolicConstant { name } => Shape::SymbolicConstant(name.clone()),
+        AST::Variable { name } => Shape::Variable(name.clone()),
+        AST::Function { name, args } => {
+            Shape::Function(name.clone(), args.iter().map(Rc::as_ptr).collect())
+        }
+        AST::BinOp { op, left, right } => Shape::BinOp(*op, Rc::as_ptr(left), Rc::as_ptr(right)),
+    };
+}
+
+impl AST {
+    /// An equal expression in which every repeated subexpression is stored once and shared.
+    pub fn hash_cons(&self) -> AST {
+        let mut interner = Interner::default();
+        let shared = self.fold(|node, children: Vec<Rc<AST>>| {
+            return interner.intern(node.with_children(children));
+        });
+        return Rc::unwrap_or_clone(shared);
+    }
+    /// Number of distinct nodes, counting a shared subexpression once. This is what the
+    /// expression costs in memory, as opposed to the length of its printed form.
+    pub fn node_count(&self) -> usize {
+        let mut seen = HashSet::new();
+        let mut count = 0;
+        let mut pending = vec![self];
+        while let Some(node) = pending.pop() {
+            count += 1;
+            for (child, is_shared) in node.links() {
+                if !is_shared || seen.insert(child as *const AST) {
+                    pending.push(child);
+                }
+            }
+        }
+        return count;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse_str;
+
+    #[test]
+    fn repeated_subexpressions_are_shared() {
+        let expr = parse_str("(x + 1) * (x + 1) + (x + 1)").unwrap();
+        assert_eq!(expr.node_count(), 11);
+        let shared = expr.hash_cons();
+        // `x`, `1`, `x + 1`, the product and the sum.
+        assert_eq!(shared.node_count(), 5);
+        assert!(shared == expr);
+        assert_eq!(shared.to_string(), expr.to_string());
+    }
+}
@@ -0,0 +1,43 @@
+use crate::tokens::Span;
+
+/// Renders `message` below the line of `source` containing `span`, with carets under the span:
+///
+/// ```text
+/// x + * 2
+///     ^ expected an operand but found operator `*`
+/// ```
+pub fn render_error(source: &str, span: Span, message: &str) -> String {
+    let start = span.start.min(source.len());
+    let end = span.end.clamp(start, source.len());
+    let line_start = source[..start].rfind('\n').map_or(0, |newline| newline + 1);
+    let line_end = source[start..].find('\n').map_or(source.len(), |newline| start + newline);
+    let column = source[line_start..start].chars().count();
+    let width = source[start..end.min(line_end)].chars().count().max(1);
+    return format!(
+        "{}\n{}{} {}",
+        &source[line_start..line_end],
+        " ".repeat(column),
+        "^".repeat(width),
+        message
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render_error;
+    use crate::Span;
+
+    #[test]
+    fn points_at_the_span_on_its_own_line() {
+        let span = Span { start: 4, end: 5 };
+        assert_eq!(
+            render_error("x + * 2", span, "expected an operand"),
+            "x + * 2\n    ^ expected an operand"
+        );
+        let span = Span { start: 6, end: 8 };
+        assert_eq!(render_error("x +\ny ** 2", span, "bad"), "y ** 2\n  ^^ bad");
+        // An empty span at the end of the input still gets one caret.
+        let span = Span { start: 3, end: 3 };
+        assert_eq!(render_error("x +", span, "eof"), "x +\n   ^ eof");
+    }
+}
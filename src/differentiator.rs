@@ -1,60 +1,326 @@
-use crate::{ast::AST, tokens::BinOp};
+use std::{error, fmt, rc::Rc};
 
-impl AST {
-    pub fn differentiate(&self) -> Self {
+use crate::{ast::AST, functions::FunctionRegistry, tokens::BinOp, Error};
+
+#[derive(Debug)]
+pub enum DifferentiationError {
+    UnknownFunction {
+        name: String,
+        arity: usize,
+    },
+    WrongArguments {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    MissingDerivative {
+        name: String,
+        argument: usize,
+    },
+    /// The expression has several variables, so the one to differentiate with respect to must
+    /// be named.
+    AmbiguousVariable {
+        variables: Vec<String>,
+    },
+}
+impl error::Error for DifferentiationError {}
+
+impl fmt::Display for DifferentiationError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
         return match self {
-            AST::Literal { .. } | AST::SymbolicConstant {..} => AST::Literal { value: 0. },
-            AST::Variable { .. } => AST::Literal { value: 1. },
-            AST::BinOp { op, left, right } => get_binop_derivative(*op, left, right),
-            AST::Function { name, args } => get_function_derivative(name, args),
+            DifferentiationError::UnknownFunction { name, arity } => write!(
+                formatter,
+                "don't know how to differentiate `{}` with {} arguments",
+                name, arity
+            ),
+            DifferentiationError::WrongArguments {
+                name,
+                expected,
+                found,
+            } => write!(
+                formatter,
+                "`{}` takes {} arguments but {} were given",
+                name, expected, found
+            ),
+            DifferentiationError::MissingDerivative { name, argument } => write!(
+                formatter,
+                "no derivative of `{}` with respect to argument {} is registered",
+                name,
+                argument + 1
+            ),
+            DifferentiationError::AmbiguousVariable { variables } => write!(
+                formatter,
+                "the expression has several variables ({}); name the one to differentiate with \
+                 respect to, e.g. `d/d{}`",
+                variables.join(", "),
+                variables.first().map_or("x", String::as_str)
+            ),
         };
     }
 }
 
-fn get_function_derivative(name: &str, args: &Vec<AST>) -> AST {
-    if args.len() != 1 {todo!();}
-    let arg = args[0].clone();
-    return arg.differentiate()
-        * match name {
-            "-" => AST::Literal{value: -1.},
-            "sin" => AST::Function {
-                name: "cos".to_string(),
-                args: vec![arg],
-            },
-            "cos" => {
-                AST::Literal { value: -1. }
-                    * AST::Function {
-                        name: "sin".to_string(),
-                        args: vec![arg],
-                    }
-            }
-            "tan" => AST::Function {
-                name: "sec".to_string(),
-                args: vec![arg],
+impl AST {
+    /// Differentiates with respect to the expression's only variable, giving `0` for a
+    /// constant. Use [`AST::differentiate_wrt`] when it has several.
+    pub fn differentiate(&self) -> Result<Self, DifferentiationError> {
+        return self.differentiate_with(FunctionRegistry::builtin());
+    }
+    /// [`AST::differentiate`] using the functions in `registry`.
+    pub fn differentiate_with(
+        &self,
+        registry: &FunctionRegistry,
+    ) -> Result<Self, DifferentiationError> {
+        return match self.only_variable()? {
+            Some(var) => self.differentiate_wrt_with(&var, registry),
+            None => Ok(AST::Literal { value: 0. }),
+        };
+    }
+    /// The variable [`AST::differentiate`] differentiates with respect to, `None` for a
+    /// constant, or [`DifferentiationError::AmbiguousVariable`] if there are several.
+    pub fn only_variable(&self) -> Result<Option<String>, DifferentiationError> {
+        let mut variables = self.variables();
+        return match variables.len() {
+            0 | 1 => Ok(variables.pop_first()),
+            _ => Err(DifferentiationError::AmbiguousVariable {
+                variables: variables.into_iter().collect(),
+            }),
+        };
+    }
+    /// Partial derivative with respect to `var`; every other variable is held constant.
+    pub fn differentiate_wrt(&self, var: &str) -> Result<Self, DifferentiationError> {
+        return self.differentiate_wrt_with(var, FunctionRegistry::builtin());
+    }
+    /// [`AST::differentiate_wrt`] using the functions in `registry`.
+    pub fn differentiate_wrt_with(
+        &self,
+        var: &str,
+        registry: &FunctionRegistry,
+    ) -> Result<Self, DifferentiationError> {
+        // Folding bottom-up hands every node the derivatives of its children, so deep
+        // expressions don't recurse. Identical subexpressions are merged first, so each is
+        // differentiated once and the result shares them instead of copying.
+        let (derivative, _) = self.hash_cons().try_fold(|node, derivatives| {
+            let depends_on_var = derivatives.iter().any(|(_, depends)| *depends);
+            let derivative = match node {
+                AST::Literal { .. } | AST::SymbolicConstant { .. } => AST::Literal { value: 0. },
+                AST::Variable { name } => AST::Literal {
+                    value: if name == var { 1. } else { 0. },
+                },
+                AST::BinOp { op, left, right } => {
+                    let mut derivatives = derivatives.into_iter();
+                    let (left, right) = (
+                        Operand::new(left, derivatives.next().unwrap()),
+                        Operand::new(right, derivatives.next().unwrap()),
+                    );
+                    get_binop_derivative(*op, left, right)
+                }
+                AST::Function { name, args } => {
+                    let derivatives = derivatives.into_iter().map(|(derivative, _)| derivative);
+                    get_function_derivative(name, args, derivatives.collect(), registry)?
+                }
+            };
+            let depends_on_var =
+                depends_on_var || matches!(node, AST::Variable { name } if name == var);
+            return Ok((Rc::new(derivative), depends_on_var));
+        })?;
+        return Ok(Rc::unwrap_or_clone(derivative));
+    }
+    /// The `n`th derivative with respect to `var`, simplified after every step so that the
+    /// intermediate expressions stay small.
+    pub fn nth_derivative(&self, var: &str, n: usize) -> Result<Self, Error> {
+        return self.nth_derivative_with(var, n, FunctionRegistry::builtin());
+    }
+    /// [`AST::nth_derivative`] using the functions in `registry`.
+    pub fn nth_derivative_with(
+        &self,
+        var: &str,
+        n: usize,
+        registry: &FunctionRegistry,
+    ) -> Result<Self, Error> {
+        return self.mixed_derivative_with(&[(var, n)], registry);
+    }
+    /// Differentiates `n` times with respect to each `(var, n)` in turn, so
+    /// `[("x", 2), ("y", 1)]` gives the third-order partial derivative `∂³/∂x²∂y`. The result
+    /// is simplified after every step.
+    pub fn mixed_derivative(&self, orders: &[(&str, usize)]) -> Result<Self, Error> {
+        return self.mixed_derivative_with(orders, FunctionRegistry::builtin());
+    }
+    /// [`AST::mixed_derivative`] using the functions in `registry`.
+    pub fn mixed_derivative_with(
+        &self,
+        orders: &[(&str, usize)],
+        registry: &FunctionRegistry,
+    ) -> Result<Self, Error> {
+        let mut derivative = self.clone();
+        for (var, n) in orders {
+            for _ in 0..*n {
+                derivative = derivative.differentiate_wrt_with(var, registry)?;
+                derivative.simplify_with(registry)?;
             }
-            .pow(AST::Literal { value: 2. }),
-            "ln" => AST::Literal { value: 1. } / arg,
-            _ => AST::Function { name: name.to_owned() + "'", args: vec![arg] },
+        }
+        return Ok(derivative);
+    }
+}
+
+/// An operand of a `BinOp` together with its derivative and whether it depends on the
+/// variable at all.
+struct Operand<'a> {
+    ast: &'a Rc<AST>,
+    derivative: Rc<AST>,
+    depends_on_var: bool,
+}
+
+impl<'a> Operand<'a> {
+    fn new(ast: &'a Rc<AST>, (derivative, depends_on_var): (Rc<AST>, bool)) -> Self {
+        return Operand {
+            ast,
+            derivative,
+            depends_on_var,
         };
+    }
+}
+
+/// Applies the chain rule over every argument: `d f(g1, .., gn) = sum(df/dgi * dgi)`.
+/// Unregistered functions of one argument differentiate to a placeholder `f'`. `derivatives`
+/// holds the derivative of each argument.
+fn get_function_derivative(
+    name: &str,
+    args: &[Rc<AST>],
+    mut derivatives: Vec<Rc<AST>>,
+    registry: &FunctionRegistry,
+) -> Result<AST, DifferentiationError> {
+    let definition = match (registry.get(name), args) {
+        (Some(definition), _) => definition,
+        (None, [arg]) => {
+            return Ok(AST::binary(
+                BinOp::Mul,
+                derivatives.remove(0),
+                AST::Function {
+                    name: name.to_owned() + "'",
+                    args: vec![Rc::clone(arg)],
+                },
+            ))
+        }
+        (None, _) => {
+            return Err(DifferentiationError::UnknownFunction {
+                name: name.to_string(),
+                arity: args.len(),
+            })
+        }
+    };
+    if definition.arity() != args.len() {
+        return Err(DifferentiationError::WrongArguments {
+            name: name.to_string(),
+            expected: definition.arity(),
+            found: args.len(),
+        });
+    }
+    let args: Vec<AST> = args.iter().map(|arg| (**arg).clone()).collect();
+    let mut terms = Vec::with_capacity(args.len());
+    for (index, derivative) in derivatives.into_iter().enumerate() {
+        let partial = definition.partial_derivative(index, &args).ok_or_else(|| {
+            DifferentiationError::MissingDerivative {
+                name: name.to_string(),
+                argument: index,
+            }
+        })?;
+        terms.push(AST::binary(BinOp::Mul, derivative, partial));
+    }
+    return Ok(terms
+        .into_iter()
+        .reduce(|sum, term| sum + term)
+        .unwrap_or(AST::Literal { value: 0. }));
 }
 
-fn get_binop_derivative(op: BinOp, left: &AST, right: &AST) -> AST {
+fn get_binop_derivative(op: BinOp, left: Operand, right: Operand) -> AST {
+    let mul = |a: Rc<AST>, b: Rc<AST>| AST::binary(BinOp::Mul, a, b);
+    let ln = |a: Rc<AST>| AST::Function {
+        name: "ln".to_string(),
+        args: vec![a],
+    };
     return match op {
-        BinOp::Add => left.differentiate() + right.differentiate(),
-        BinOp::Sub => left.differentiate() - right.differentiate(),
-        BinOp::Mul => left.clone() * right.differentiate() + right.clone() * left.differentiate(),
-        BinOp::Div => {
-            (right.clone() * left.differentiate() - left.clone() * right.differentiate())
-                / right.clone().pow(AST::Literal { value: 2. })
+        BinOp::Add => AST::binary(BinOp::Add, left.derivative, right.derivative),
+        BinOp::Sub => AST::binary(BinOp::Sub, left.derivative, right.derivative),
+        BinOp::Mul => {
+            mul(Rc::clone(left.ast), right.derivative) + mul(Rc::clone(right.ast), left.derivative)
         }
-        BinOp::Pow => {
-            left.clone().pow(right.clone())
-                * (right.differentiate()
-                    * AST::Function {
-                        name: "ln".to_string(),
-                        args: vec![left.clone()],
-                    }
-                    + right.clone() * left.differentiate() / left.clone())
+        BinOp::Div => {
+            (mul(Rc::clone(right.ast), left.derivative)
+                - mul(Rc::clone(left.ast), right.derivative))
+                / AST::binary(BinOp::Pow, Rc::clone(right.ast), AST::Literal { value: 2. })
         }
+        BinOp::Pow => match (left.depends_on_var, right.depends_on_var) {
+            (false, false) => AST::Literal { value: 0. },
+            // n x^(n-1) x'
+            (true, false) => {
+                let exponent =
+                    AST::binary(BinOp::Sub, Rc::clone(right.ast), AST::Literal { value: 1. });
+                mul(
+                    Rc::clone(right.ast),
+                    Rc::new(AST::binary(BinOp::Pow, Rc::clone(left.ast), exponent)),
+                ) * left.derivative
+            }
+            // a^g ln(a) g'
+            (false, true) => {
+                AST::binary(BinOp::Pow, Rc::clone(left.ast), Rc::clone(right.ast))
+                    * ln(Rc::clone(left.ast))
+                    * right.derivative
+            }
+            // f^g (g' ln(f) + g f' / f)
+            (true, true) => {
+                AST::binary(BinOp::Pow, Rc::clone(left.ast), Rc::clone(right.ast))
+                    * (AST::binary(BinOp::Mul, right.derivative, ln(Rc::clone(left.ast)))
+                        + mul(Rc::clone(right.ast), left.derivative) / Rc::clone(left.ast))
+            }
+        },
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{ast::call, differentiate_str, parse_str, DifferentiationError};
+
+    #[test]
+    fn differentiates_with_respect_to_the_only_variable() {
+        assert_eq!(differentiate_str("t^3").unwrap().to_string(), "3 * t ^ 2");
+        assert_eq!(differentiate_str("x - x").unwrap().to_string(), "0");
+    }
+
+    #[test]
+    fn several_variables_need_an_explicit_one() {
+        let err = parse_str("t^2 + s").unwrap().differentiate().unwrap_err();
+        assert!(matches!(
+            err,
+            DifferentiationError::AmbiguousVariable { variables } if variables == ["s", "t"]
+        ));
+        let mut derivative = parse_str("t^2 + s")
+            .unwrap()
+            .differentiate_wrt("s")
+            .unwrap();
+        derivative.simplify().unwrap();
+        assert_eq!(derivative.to_string(), "1");
+    }
+
+    #[test]
+    fn constants_differentiate_to_zero() {
+        for source in ["5", "pi", "0x1F", "sin(2) * e"] {
+            assert_eq!(differentiate_str(source).unwrap().to_string(), "0");
+        }
+    }
+
+    #[test]
+    fn deep_negation_chains_do_not_overflow() {
+        for depth in [50_000, 50_001] {
+            let mut ast = parse_str("x^2").unwrap();
+            for _ in 0..depth {
+                ast = call("-", ast);
+            }
+            let mut derivative = ast.differentiate_wrt("x").unwrap();
+            derivative.simplify().unwrap();
+            let expected = if depth % 2 == 0 { "2 * x" } else { "-2 * x" };
+            assert_eq!(derivative.to_string(), expected);
+            ast.simplify().unwrap();
+            assert_eq!(ast.hash_cons().node_count(), depth + 3);
+        }
+    }
+}
@@ -0,0 +1,430 @@
+use std::{collections::HashMap, error, fmt};
+
+use crate::{
+    ast::{call, is_zero, literal, negate, AST, ZERO_TOLERANCE},
+    functions::FunctionRegistry,
+    polynomial::{add_polynomials, multiply_polynomials},
+    tokens::BinOp,
+    Error,
+};
+
+/// Two expressions that are claimed to be equal, as parsed from `left = right`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Equation {
+    pub left: AST,
+    pub right: AST,
+}
+
+impl fmt::Display for Equation {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(formatter, "{} = {}", self.left, self.right);
+    }
+}
+
+#[derive(Debug)]
+pub enum SolveError {
+    /// The variable does not occur in the equation.
+    MissingVariable { var: String },
+    /// Both sides are equal whatever the value of `var`.
+    Identity { var: String },
+    /// The equation is neither a polynomial of degree at most 2 nor can `var` be isolated by
+    /// inverting the operations around it.
+    Unsupported { equation: String, var: String },
+}
+impl error::Error for SolveError {}
+
+impl fmt::Display for SolveError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            SolveError::MissingVariable { var } => {
+                write!(formatter, "`{}` does not occur in the equation", var)
+            }
+            SolveError::Identity { var } => {
+                write!(formatter, "the equation holds for every value of `{}`", var)
+            }
+            SolveError::Unsupported { equation, var } => {
+                write!(
+                    formatter,
+                    "don't know how to solve `{}` for `{}`",
+                    equation, var
+                )
+            }
+        };
+    }
+}
+
+/// How closely the two sides must agree, relative to their size, for a numeric solution to be
+/// kept. Solutions that fail this, such as `x = 1` for `sqrt(x) = -1`, came from inverting a
+/// function outside its range.
+const CHECK_TOLERANCE: f64 = 1e-9;
+
+/// The real solutions of `equation` for `var`, simplified. Numeric solutions are in ascending
+/// order, and an empty list means there are none.
+///
+/// Polynomials of degree 1 and 2 are solved directly, using the quadratic formula for the
+/// latter; symbolic coefficients are assumed to be nonzero. Anything else is solved by
+/// isolating `var`, which must then occur only once: operators are undone one at a time and
+/// functions are replaced by their inverses. Even powers, `abs` and `cosh` give both signs,
+/// while periodic functions such as `sin` only give the principal solution.
+pub fn solve(equation: &Equation, var: &str) -> Result<Vec<AST>, Error> {
+    return solve_with(equation, var, FunctionRegistry::builtin());
+}
+
+/// [`solve`] using the functions in `registry`.
+pub fn solve_with(
+    equation: &Equation,
+    var: &str,
+    registry: &FunctionRegistry,
+) -> Result<Vec<AST>, Error> {
+    let solver = Solver { var, registry };
+    let difference = equation.left.clone() - equation.right.clone();
+    if !difference.contains_variable(var) {
+        return Err(SolveError::MissingVariable {
+            var: var.to_string(),
+        }
+        .into());
+    }
+    let candidates = match solver.polynomial(&difference)? {
+        Some(candidates) => candidates,
+        None => match solver.isolate(equation) {
+            Some(candidates) => candidates,
+            None => {
+                return Err(SolveError::Unsupported {
+                    equation: equation.to_string(),
+                    var: var.to_string(),
+                }
+                .into())
+            }
+        },
+    };
+    let mut solutions: Vec<(AST, Option<f64>)> = Vec::new();
+    for mut candidate in candidates {
+        candidate.simplify_with(registry)?;
+        let value = candidate.evaluate_with(&HashMap::new(), registry).ok();
+        if !solver.satisfies(equation, value) {
+            continue;
+        }
+        if !solutions.iter().any(|(solution, _)| *solution == candidate) {
+            solutions.push((candidate, value));
+        }
+    }
+    solutions.sort_by(|(_, a), (_, b)| match (a, b) {
+        (Some(a), Some(b)) => a.total_cmp(b),
+        _ => std::cmp::Ordering::Equal,
+    });
+    return Ok(solutions
+        .into_iter()
+        .map(|(solution, _)| solution)
+        .collect());
+}
+
+struct Solver<'a> {
+    var: &'a str,
+    registry: &'a FunctionRegistry,
+}
+
+impl Solver<'_> {
+    /// Solves `difference = 0` if it is a polynomial in `var` of degree at most 2, returning
+    /// `None` if it is not one.
+    fn polynomial(&self, difference: &AST) -> Result<Option<Vec<AST>>, Error> {
+        let mut coefficients = match self.coefficients(difference) {
+            Some(coefficients) => coefficients,
+            None => return Ok(None),
+        };
+        for coefficient in &mut coefficients {
+            coefficient.simplify_with(self.registry)?;
+        }
+        // Rounding can leave a tiny leading coefficient, which would turn a linear equation
+        // into a quadratic one.
+        while coefficients
+            .last()
+            .is_some_and(|c| is_zero(c, self.registry))
+        {
+            coefficients.pop();
+        }
+        return Ok(Some(match coefficients.as_slice() {
+            [] => {
+                return Err(SolveError::Identity {
+                    var: self.var.to_string(),
+                }
+                .into())
+            }
+            [_] => Vec::new(),
+            [c, b] => vec![negate(c.clone()) / b.clone()],
+            [c, b, a] => self.quadratic(a, b, c)?,
+            _ => return Ok(None),
+        }));
+    }
+    /// The roots of `a x^2 + b x + c` by the quadratic formula.
+    fn quadratic(&self, a: &AST, b: &AST, c: &AST) -> Result<Vec<AST>, Error> {
+        let two_a = literal(2.) * a.clone();
+        let mut discriminant = b.clone().pow(literal(2.)) - literal(4.) * a.clone() * c.clone();
+        discriminant.simplify_with(self.registry)?;
+        let vertex = negate(b.clone()) / two_a.clone();
+        if discriminant.variables().is_empty() {
+            if let Ok(value) = discriminant.evaluate_with(&HashMap::new(), self.registry) {
+                if value.abs() < ZERO_TOLERANCE {
+                    return Ok(vec![vertex]);
+                }
+                if value < 0. {
+                    return Ok(Vec::new());
+                }
+            }
+        }
+        let root = match discriminant.evaluate_with(&HashMap::new(), self.registry) {
+            Ok(value) if value.sqrt().fract() == 0. => literal(value.sqrt()),
+            _ => call("sqrt", discriminant),
+        };
+        return Ok(vec![
+            (negate(b.clone()) - root.clone()) / two_a.clone(),
+            (negate(b.clone()) + root) / two_a,
+        ]);
+    }
+    /// Coefficients of `ast` as a polynomial in `var`, lowest power first, where anything
+    /// without `var` counts as a coefficient. `None` if it is not a polynomial of degree at
+    /// most 2.
+    fn coefficients(&self, ast: &AST) -> Option<Vec<AST>> {
+        // `None` marks a subexpression without `var`, which is its own constant coefficient.
+        let polynomial = ast.try_fold(|node, children: Vec<Option<Vec<AST>>>| {
+            if let AST::Variable { name } = node {
+                return Ok(if name == self.var {
+                    Some(vec![literal(0.), literal(1.)])
+                } else {
+                    None
+                });
+            }
+            if children.iter().all(Option::is_none) {
+                return Ok(None);
+            }
+            let operands = node.children();
+            let children: Vec<Vec<AST>> = children
+                .into_iter()
+                .zip(&operands)
+                .map(|(child, operand)| child.unwrap_or_else(|| vec![(*operand).clone()]))
+                .collect();
+            let polynomial = match node {
+                AST::Function { name, .. } if name == "-" => {
+                    children[0].iter().cloned().map(negate).collect()
+                }
+                AST::BinOp { op, right, .. } => {
+                    let (left, right_coefficients) = (&children[0], &children[1]);
+                    match op {
+                        BinOp::Add => add_polynomials(left, right_coefficients, false),
+                        BinOp::Sub => add_polynomials(left, right_coefficients, true),
+                        BinOp::Mul if left.len() + right_coefficients.len() <= 4 => {
+                            multiply_polynomials(left, right_coefficients)
+                        }
+                        BinOp::Div if right_coefficients.len() == 1 => {
+                            left.iter().map(|c| c.clone() / (**right).clone()).collect()
+                        }
+                        BinOp::Pow => match &**right {
+                            AST::Literal { value } if *value == 1. => left.clone(),
+                            AST::Literal { value } if *value == 2. && left.len() <= 2 => {
+                                multiply_polynomials(left, left)
+                            }
+                            _ => return Err(()),
+                        },
+                        _ => return Err(()),
+                    }
+                }
+                _ => return Err(()),
+            };
+            return Ok(Some(polynomial));
+        });
+        return match polynomial {
+            Ok(Some(polynomial)) => Some(polynomial),
+            Ok(None) => Some(vec![ast.clone()]),
+            Err(()) => None,
+        };
+    }
+    /// Undoes the operations around `var` one at a time, carrying them over to the other
+    /// side. Returns `None` if `var` occurs more than once or is inside something that cannot
+    /// be inverted.
+    fn isolate(&self, equation: &Equation) -> Option<Vec<AST>> {
+        let (side, other) = match (
+            equation.left.contains_variable(self.var),
+            equation.right.contains_variable(self.var),
+        ) {
+            (true, false) => (&equation.left, &equation.right),
+            (false, true) => (&equation.right, &equation.left),
+            _ => return None,
+        };
+        let mut pending = vec![(side.clone(), other.clone())];
+        let mut solutions = Vec::new();
+        while let Some((side, target)) = pending.pop() {
+            match &side {
+                AST::Variable { .. } => solutions.push(target),
+                AST::BinOp { op, left, right } => {
+                    let (left, right) = ((**left).clone(), (**right).clone());
+                    match (
+                        left.contains_variable(self.var),
+                        right.contains_variable(self.var),
+                    ) {
+                        (true, false) => {
+                            pending.extend(self.invert_left(*op, left, right, target)?)
+                        }
+                        (false, true) => pending.push(match op {
+                            BinOp::Add => (right, target - left),
+                            BinOp::Sub => (right, left - target),
+                            BinOp::Mul => (right, target / left),
+                            BinOp::Div => (right, left / target),
+                            BinOp::Pow => (right, call("ln", target) / call("ln", left)),
+                        }),
+                        _ => return None,
+                    }
+                }
+                AST::Function { name, args } if args.len() == 1 => {
+                    let arg = (*args[0]).clone();
+                    pending.extend(
+                        inverse(name, target)?
+                            .into_iter()
+                            .map(|target| (arg.clone(), target)),
+                    );
+                }
+                _ => return None,
+            }
+        }
+        return Some(solutions);
+    }
+    /// Inverts `left op right = target` for `left`, which holds `var`.
+    fn invert_left(
+        &self,
+        op: BinOp,
+        left: AST,
+        right: AST,
+        target: AST,
+    ) -> Option<Vec<(AST, AST)>> {
+        let target = match op {
+            BinOp::Add => target - right,
+            BinOp::Sub => target + right,
+            BinOp::Mul => target / right,
+            BinOp::Div => target * right,
+            BinOp::Pow => {
+                return Some(
+                    self.roots(target, right)
+                        .into_iter()
+                        .map(|target| (left.clone(), target))
+                        .collect(),
+                )
+            }
+        };
+        return Some(vec![(left, target)]);
+    }
+    /// The real `exponent`-th roots of `target`: both signs for even integer exponents, and
+    /// the real cube root and the like of negative numbers for odd ones.
+    fn roots(&self, target: AST, exponent: AST) -> Vec<AST> {
+        let empty = HashMap::new();
+        let n = exponent.evaluate_with(&empty, self.registry).ok();
+        let value = target.evaluate_with(&empty, self.registry).ok();
+        let is_integer = n.is_some_and(|n| n.fract() == 0. && n != 0.);
+        if let (Some(n), Some(value), true) = (n, value, is_integer) {
+            let even = n % 2. == 0.;
+            if even && value < 0. {
+                return Vec::new();
+            }
+            let root = value.abs().powf(1. / n);
+            return if even {
+                vec![literal(-root), literal(root)]
+            } else {
+                vec![literal(root.copysign(value))]
+            };
+        }
+        let root = target.pow(literal(1.) / exponent);
+        return match n {
+            Some(n) if is_integer && n % 2. == 0. => vec![negate(root.clone()), root],
+            _ => vec![root],
+        };
+    }
+    /// Whether a candidate that evaluates to `value` is a real solution. Only equations without
+    /// other variables can be checked; solutions of the rest are kept.
+    fn satisfies(&self, equation: &Equation, value: Option<f64>) -> bool {
+        let mut variables = equation.left.variables();
+        variables.append(&mut equation.right.variables());
+        if variables.len() > 1 {
+            return true;
+        }
+        let value = match value {
+            Some(value) => value,
+            // Undefined, e.g. `asin(2)` for `sin(x) = 2`.
+            None => return false,
+        };
+        if !value.is_finite() {
+            return false;
+        }
+        let env = HashMap::from([(self.var.to_string(), value)]);
+        return match (
+            equation.left.evaluate_with(&env, self.registry),
+            equation.right.evaluate_with(&env, self.registry),
+        ) {
+            (Ok(left), Ok(right)) => {
+                (left - right).abs() <= CHECK_TOLERANCE * left.abs().max(right.abs()).max(1.)
+            }
+            _ => false,
+        };
+    }
+}
+
+/// The values of `arg` for which `name(arg) = target`, or `None` if `name` has no inverse.
+fn inverse(name: &str, target: AST) -> Option<Vec<AST>> {
+    let both_signs = |value: AST| vec![negate(value.clone()), value];
+    return Some(match name {
+        "-" => vec![negate(target)],
+        "exp" => vec![call("ln", target)],
+        "ln" => vec![call("exp", target)],
+        "log10" => vec![literal(10.).pow(target)],
+        "log2" => vec![literal(2.).pow(target)],
+        "sqrt" => vec![target.pow(literal(2.))],
+        "abs" => both_signs(target),
+        "sin" => vec![call("asin", target)],
+        "cos" => vec![call("acos", target)],
+        "tan" => vec![call("atan", target)],
+        "asin" => vec![call("sin", target)],
+        "acos" => vec![call("cos", target)],
+        "atan" => vec![call("tan", target)],
+        "sinh" => vec![call("asinh", target)],
+        "cosh" => both_signs(call("acosh", target)),
+        "tanh" => vec![call("atanh", target)],
+        "asinh" => vec![call("sinh", target)],
+        "acosh" => vec![call("cosh", target)],
+        "atanh" => vec![call("tanh", target)],
+        _ => return None,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::solve;
+    use crate::{parse_equation_str, Error};
+
+    fn solutions(source: &str) -> Result<Vec<String>, Error> {
+        let equation = parse_equation_str(source).unwrap();
+        let solutions = solve(&equation, "x")?;
+        return Ok(solutions
+            .iter()
+            .map(|solution| solution.to_string())
+            .collect());
+    }
+
+    #[test]
+    fn solves_polynomials() {
+        assert_eq!(solutions("x^2 - 5*x + 6 = 0").unwrap(), ["2", "3"]);
+        assert_eq!(solutions("2*x + 3 = 7").unwrap(), ["2"]);
+        // Complex roots only.
+        assert!(solutions("x^2 + 2*x + 5 = 0").unwrap().is_empty());
+        assert!(solutions("x^2 = -4").unwrap().is_empty());
+    }
+
+    #[test]
+    fn inverts_functions() {
+        assert_eq!(solutions("exp(x) = 5").unwrap(), ["ln(5)"]);
+        assert_eq!(solutions("ln(x) = 2").unwrap(), ["exp(2)"]);
+        assert_eq!(solutions("sin(x) = 0.5").unwrap(), ["asin(0.5)"]);
+        // Squaring both sides gives `x = 1`, which does not satisfy the equation.
+        assert!(solutions("sqrt(x) = -1").unwrap().is_empty());
+    }
+
+    #[test]
+    fn transcendental_equations_are_unsupported() {
+        assert!(solutions("sin(x) + x = 1").is_err());
+    }
+}
@@ -0,0 +1,96 @@
+use std::{collections::HashMap, f64::consts};
+
+use crate::{ast::AST, functions::FunctionRegistry, simplifier::EvaluationError, tokens::BinOp};
+
+impl AST {
+    /// Numerically evaluates the expression, looking variables up in `env`.
+    pub fn evaluate(&self, env: &HashMap<String, f64>) -> Result<f64, EvaluationError> {
+        return self.evaluate_with(env, FunctionRegistry::builtin());
+    }
+    /// [`AST::evaluate`] using the functions in `registry`.
+    pub fn evaluate_with(
+        &self,
+        env: &HashMap<String, f64>,
+        registry: &FunctionRegistry,
+    ) -> Result<f64, EvaluationError> {
+        return self.try_fold(|node, values| match node {
+            AST::Literal { value } => Ok(*value),
+            AST::SymbolicConstant { name } => get_constant_value(name),
+            AST::Variable { name } => env
+                .get(name)
+                .copied()
+                .ok_or_else(|| EvaluationError::UnboundVariable(name.clone())),
+            AST::BinOp { op, .. } => evaluate_binop(*op, values[0], values[1]),
+            AST::Function { name, args } => {
+                let definition = registry
+                    .get(name)
+                    .ok_or_else(|| EvaluationError::UnknownFunction(name.clone()))?;
+                if definition.arity() != args.len() {
+                    return Err(EvaluationError::WrongArguments);
+                }
+                definition.evaluate(&values)
+            }
+        });
+    }
+}
+
+fn get_constant_value(name: &str) -> Result<f64, EvaluationError> {
+    return match name {
+        "e" => Ok(consts::E),
+        "pi" => Ok(consts::PI),
+        _ => Err(EvaluationError::UnknownConstant(name.to_string())),
+    };
+}
+
+fn evaluate_binop(op: BinOp, left: f64, right: f64) -> Result<f64, EvaluationError> {
+    return match op {
+        BinOp::Add => Ok(left + right),
+        BinOp::Sub => Ok(left - right),
+        BinOp::Mul => Ok(left * right),
+        BinOp::Div if right == 0. => Err(EvaluationError::ZeroDivisionError),
+        BinOp::Div => Ok(left / right),
+        BinOp::Pow if left < 0. && right.fract() != 0. => Err(EvaluationError::DomainError {
+            function: "^".to_string(),
+            argument: left,
+        }),
+        BinOp::Pow if left == 0. && right < 0. => Err(EvaluationError::ZeroDivisionError),
+        BinOp::Pow => Ok(left.powf(right)),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{parse_str, EvaluationError};
+
+    fn evaluate(source: &str) -> Result<f64, EvaluationError> {
+        let env = HashMap::from([("x".to_string(), 2.), ("y".to_string(), 3.)]);
+        return parse_str(source).unwrap().evaluate(&env);
+    }
+
+    #[test]
+    fn values() {
+        assert_eq!(evaluate("x^2 + y").unwrap(), 7.);
+        assert_eq!(evaluate("-x * (y - 1) / 4").unwrap(), -1.);
+        assert_eq!(evaluate("pi").unwrap(), std::f64::consts::PI);
+        assert_eq!(evaluate("e").unwrap(), std::f64::consts::E);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(
+            evaluate("1 / 0"),
+            Err(EvaluationError::ZeroDivisionError)
+        ));
+        assert!(matches!(
+            evaluate("ln(-1)"),
+            Err(EvaluationError::DomainError { function, argument })
+                if function == "ln" && argument == -1.
+        ));
+        assert!(matches!(
+            evaluate("z + 1"),
+            Err(EvaluationError::UnboundVariable(name)) if name == "z"
+        ));
+    }
+}
@@ -0,0 +1,173 @@
+use std::{collections::HashMap, fmt, sync::OnceLock};
+
+use crate::{ast::AST, simplifier::EvaluationError};
+
+pub type NumericFn = Box<dyn Fn(&[f64]) -> Result<f64, EvaluationError> + Send + Sync>;
+pub type DerivativeFn = Box<dyn Fn(&[AST]) -> AST + Send + Sync>;
+pub type SpecialValueFn = Box<dyn Fn(&[AST]) -> Option<AST> + Send + Sync>;
+pub type AntiderivativeFn = Box<dyn Fn(&AST) -> AST + Send + Sync>;
+
+/// Everything the engine knows about a named function: how many arguments it takes, how to
+/// compute it, its partial derivatives, any values it should simplify to exactly and, for
+/// unary functions, its antiderivative.
+pub struct FunctionDefinition {
+    arity: usize,
+    evaluate: NumericFn,
+    derivatives: Vec<DerivativeFn>,
+    special_values: Option<SpecialValueFn>,
+    antiderivative: Option<AntiderivativeFn>,
+}
+
+impl FunctionDefinition {
+    /// A function of `arity` arguments computed by `evaluate`, with no derivative rules or
+    /// special values yet.
+    pub fn new<F>(arity: usize, evaluate: F) -> Self
+    where
+        F: Fn(&[f64]) -> Result<f64, EvaluationError> + Send + Sync + 'static,
+    {
+        return FunctionDefinition {
+            arity,
+            evaluate: Box::new(evaluate),
+            derivatives: Vec::new(),
+            special_values: None,
+            antiderivative: None,
+        };
+    }
+    /// Shorthand for [`FunctionDefinition::new`] with a single argument.
+    pub fn unary<F>(evaluate: F) -> Self
+    where
+        F: Fn(f64) -> Result<f64, EvaluationError> + Send + Sync + 'static,
+    {
+        return FunctionDefinition::new(1, move |args| evaluate(args[0]));
+    }
+    /// Adds the partial derivative with respect to the next argument, in order. The rule
+    /// receives the function's arguments and returns the partial derivative at them.
+    pub fn derivative<F>(mut self, derivative: F) -> Self
+    where
+        F: Fn(&[AST]) -> AST + Send + Sync + 'static,
+    {
+        self.derivatives.push(Box::new(derivative));
+        return self;
+    }
+    /// Sets the rule the simplifier uses to replace calls with exact values, e.g. `sin(pi) = 0`.
+    pub fn special_values<F>(mut self, special_values: F) -> Self
+    where
+        F: Fn(&[AST]) -> Option<AST> + Send + Sync + 'static,
+    {
+        self.special_values = Some(Box::new(special_values));
+        return self;
+    }
+    /// Sets the antiderivative of a unary function: given `u`, the rule returns `∫ f(u) du`
+    /// without the constant of integration, e.g. `-cos(u)` for `sin`.
+    pub fn antiderivative<F>(mut self, antiderivative: F) -> Self
+    where
+        F: Fn(&AST) -> AST + Send + Sync + 'static,
+    {
+        self.antiderivative = Some(Box::new(antiderivative));
+        return self;
+    }
+    pub fn arity(&self) -> usize {
+        return self.arity;
+    }
+    pub(crate) fn evaluate(&self, args: &[f64]) -> Result<f64, EvaluationError> {
+        return (self.evaluate)(args);
+    }
+    /// Partial derivative with respect to argument `index`, if a rule was registered for it.
+    pub(crate) fn partial_derivative(&self, index: usize, args: &[AST]) -> Option<AST> {
+        return self
+            .derivatives
+            .get(index)
+            .map(|derivative| derivative(args));
+    }
+    pub(crate) fn special_value(&self, args: &[AST]) -> Option<AST> {
+        return self
+            .special_values
+            .as_ref()
+            .and_then(|special_values| special_values(args));
+    }
+    /// `∫ f(u) du` at `u`, if an antiderivative was registered.
+    pub(crate) fn antiderivative_at(&self, u: &AST) -> Option<AST> {
+        return self
+            .antiderivative
+            .as_ref()
+            .map(|antiderivative| antiderivative(u));
+    }
+}
+
+/// A set of named functions known to the evaluator, simplifier and differentiator.
+///
+/// [`FunctionRegistry::builtin`] holds the elementary functions and is what the plain
+/// [`AST::evaluate`], [`AST::simplify`] and [`AST::differentiate_wrt`] use. To add
+/// domain-specific functions, start from [`FunctionRegistry::with_builtins`], register them,
+/// and pass the registry to the `_with` variants of those methods.
+#[derive(Default)]
+pub struct FunctionRegistry {
+    functions: HashMap<String, FunctionDefinition>,
+}
+
+/// Lists the registered names, since the definitions themselves are closures.
+impl fmt::Debug for FunctionRegistry {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let mut names: Vec<&String> = self.functions.keys().collect();
+        names.sort();
+        return formatter
+            .debug_struct("FunctionRegistry")
+            .field("functions", &names)
+            .finish();
+    }
+}
+
+impl FunctionRegistry {
+    /// An empty registry.
+    pub fn new() -> Self {
+        return FunctionRegistry::default();
+    }
+    /// A registry holding the built-in functions, ready to be extended.
+    pub fn with_builtins() -> Self {
+        let mut registry = FunctionRegistry::new();
+        crate::builtins::register_builtins(&mut registry);
+        return registry;
+    }
+    /// The shared registry of built-in functions.
+    pub fn builtin() -> &'static FunctionRegistry {
+        static BUILTIN: OnceLock<FunctionRegistry> = OnceLock::new();
+        return BUILTIN.get_or_init(FunctionRegistry::with_builtins);
+    }
+    /// Registers `definition` under `name`, returning the definition it replaced, if any.
+    pub fn register(
+        &mut self,
+        name: &str,
+        definition: FunctionDefinition,
+    ) -> Option<FunctionDefinition> {
+        return self.functions.insert(name.to_string(), definition);
+    }
+    pub fn get(&self, name: &str) -> Option<&FunctionDefinition> {
+        return self.functions.get(name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{FunctionDefinition, FunctionRegistry};
+    use crate::{parse_str, AST};
+
+    #[test]
+    fn registered_functions_evaluate_and_differentiate() {
+        let mut registry = FunctionRegistry::with_builtins();
+        let cube = FunctionDefinition::unary(|x| Ok(x * x * x)).derivative(|args| {
+            AST::Literal { value: 3. } * args[0].clone().pow(AST::Literal { value: 2. })
+        });
+        assert!(registry.register("cube", cube).is_none());
+        assert_eq!(registry.get("cube").unwrap().arity(), 1);
+        assert!(FunctionRegistry::new().get("sin").is_none());
+
+        let expr = parse_str("cube(2*x)").unwrap();
+        let env = HashMap::from([("x".to_string(), 1.5)]);
+        assert_eq!(expr.evaluate_with(&env, &registry).unwrap(), 27.);
+        let mut derivative = expr.differentiate_wrt_with("x", &registry).unwrap();
+        derivative.simplify_with(&registry).unwrap();
+        assert_eq!(derivative.evaluate_with(&env, &registry).unwrap(), 54.);
+    }
+}
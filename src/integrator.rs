@@ -0,0 +1,582 @@
+use std::{collections::HashMap, error, fmt};
+
+use crate::{
+    ast::{call, invert, negate, AST},
+    functions::FunctionRegistry,
+    polynomial::{add_polynomials, multiply_polynomials},
+    tokens::BinOp,
+    Error,
+};
+
+#[derive(Debug)]
+pub enum IntegrationError {
+    /// None of the integration rules applies to `integrand`.
+    Unsupported { integrand: String },
+    /// Quadrature hit a point where the integrand is infinite or NaN.
+    NotFinite { at: f64 },
+    /// The definite integral does not converge because of a singularity near `near`.
+    Divergent { near: f64 },
+    /// A bound of a definite integral is NaN.
+    NanBound,
+}
+impl error::Error for IntegrationError {}
+
+impl fmt::Display for IntegrationError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            IntegrationError::Unsupported { integrand } => {
+                write!(formatter, "don't know how to integrate `{}`", integrand)
+            }
+            IntegrationError::NotFinite { at } => {
+                write!(formatter, "integrand is not finite at {}", at)
+            }
+            IntegrationError::Divergent { near } => {
+                write!(formatter, "integral diverges near {}", near)
+            }
+            IntegrationError::NanBound => {
+                write!(formatter, "the bounds of an integral must not be NaN")
+            }
+        };
+    }
+}
+
+/// How many times integration by parts may be nested. Enough for `x^5 * sin(x)`, and it stops
+/// integrands such as `exp(x) * sin(x)` from cycling forever.
+const MAX_PARTS_DEPTH: usize = 6;
+
+/// Highest power of a polynomial that is expanded and integrated term by term.
+const MAX_POLYNOMIAL_DEGREE: usize = 32;
+
+/// Where an antiderivative is checked against the integrand when the simplifier cannot show
+/// that their difference vanishes. Chosen away from the integers, where poles tend to be.
+const CHECK_POINTS: [f64; 8] = [-2.3, -0.7, -0.3, 0.4, 0.9, 1.7, 2.6, 5.3];
+
+/// Relative error allowed between the derivative of an antiderivative and the integrand at a
+/// check point.
+const CHECK_TOLERANCE: f64 = 1e-9;
+
+impl AST {
+    /// An antiderivative with respect to `var`, without the constant of integration.
+    ///
+    /// Handles polynomials, linearity over sums and constant factors, powers and registered
+    /// functions of a linear argument (e.g. `cos(3x + 1)`), u-substitution for integrands of
+    /// the form `f'(g(x)) g'(x)` and integration by parts for products such as `x * exp(x)`
+    /// or `x^2 * ln(x)`. Anything else is an [`IntegrationError::Unsupported`].
+    pub fn integrate(&self, var: &str) -> Result<AST, Error> {
+        return self.integrate_with(var, FunctionRegistry::builtin());
+    }
+    /// [`AST::integrate`] using the antiderivatives of the functions in `registry`.
+    pub fn integrate_with(&self, var: &str, registry: &FunctionRegistry) -> Result<AST, Error> {
+        let mut integrand = self.clone();
+        integrand.simplify_with(registry)?;
+        let integrator = Integrator { var, registry };
+        let mut antiderivative = match integrator.integrate(&integrand, 0)? {
+            Some(antiderivative) => antiderivative,
+            None => {
+                return Err(IntegrationError::Unsupported {
+                    integrand: self.to_string(),
+                }
+                .into())
+            }
+        };
+        antiderivative.simplify_with(registry)?;
+        if !integrator.verify(&antiderivative, &integrand)? {
+            return Err(IntegrationError::Unsupported {
+                integrand: self.to_string(),
+            }
+            .into());
+        }
+        return Ok(antiderivative);
+    }
+}
+
+struct Integrator<'a> {
+    var: &'a str,
+    registry: &'a FunctionRegistry,
+}
+
+/// Every rule returns `Ok(None)` when it does not apply, so that the next one can be tried.
+impl Integrator<'_> {
+    fn integrate(&self, integrand: &AST, depth: usize) -> Result<Option<AST>, Error> {
+        if !integrand.contains_variable(self.var) {
+            return Ok(Some(integrand.clone() * self.variable()));
+        }
+        if let Some(coefficients) = as_polynomial(integrand, self.var) {
+            return Ok(Some(integrate_polynomial(&coefficients, self.variable())));
+        }
+        return match integrand {
+            AST::BinOp {
+                op: BinOp::Add | BinOp::Sub,
+                ..
+            } => self.integrate_sum(integrand, depth),
+            AST::BinOp {
+                op: BinOp::Mul | BinOp::Div,
+                ..
+            } => self.integrate_product(integrand, depth),
+            AST::Function { name, args } if name == "-" && args.len() == 1 => {
+                Ok(self.integrate(&args[0], depth)?.map(negate))
+            }
+            _ => self.integrate_factor(integrand),
+        };
+    }
+    /// Whether `antiderivative` differentiates back to `integrand`. When simplifying the
+    /// difference does not give `0`, it is evaluated at [`CHECK_POINTS`] instead, since the
+    /// simplifier cannot show identities such as `sign(x) / abs(x) = 1 / x`. Other variables
+    /// are given fixed values.
+    fn verify(&self, antiderivative: &AST, integrand: &AST) -> Result<bool, Error> {
+        let derivative = match antiderivative.differentiate_wrt_with(self.var, self.registry) {
+            Ok(derivative) => derivative,
+            Err(_) => return Ok(false),
+        };
+        let mut difference = derivative - integrand.clone();
+        difference.simplify_with(self.registry)?;
+        if difference == (AST::Literal { value: 0. }) {
+            return Ok(true);
+        }
+        let mut env: HashMap<String, f64> = difference
+            .variables()
+            .into_iter()
+            .zip(1..)
+            .map(|(name, index)| (name, 1. + 0.37 * index as f64))
+            .collect();
+        let mut checked = 0;
+        for point in CHECK_POINTS {
+            env.insert(self.var.to_string(), point);
+            let (difference, expected) = match (
+                difference.evaluate_with(&env, self.registry),
+                integrand.evaluate_with(&env, self.registry),
+            ) {
+                (Ok(difference), Ok(expected))
+                    if difference.is_finite() && expected.is_finite() =>
+                {
+                    (difference, expected)
+                }
+                _ => continue,
+            };
+            if difference.abs() > CHECK_TOLERANCE * (1. + expected.abs()) {
+                return Ok(false);
+            }
+            checked += 1;
+        }
+        return Ok(checked > 0);
+    }
+    /// Linearity: integrates a `+`/`-` chain term by term.
+    fn integrate_sum(&self, sum: &AST, depth: usize) -> Result<Option<AST>, Error> {
+        let mut pending = vec![(sum, false)];
+        let mut antiderivative: Option<AST> = None;
+        while let Some((ast, negated)) = pending.pop() {
+            match ast {
+                AST::BinOp {
+                    op: BinOp::Add,
+                    left,
+                    right,
+                } => {
+                    pending.push((right, negated));
+                    pending.push((left, negated));
+                }
+                AST::BinOp {
+                    op: BinOp::Sub,
+                    left,
+                    right,
+                } => {
+                    pending.push((right, !negated));
+                    pending.push((left, negated));
+                }
+                _ => {
+                    let term = match self.integrate(ast, depth)? {
+                        Some(term) => term,
+                        None => return Ok(None),
+                    };
+                    antiderivative = Some(match (antiderivative, negated) {
+                        (None, false) => term,
+                        (None, true) => negate(term),
+                        (Some(sum), false) => sum + term,
+                        (Some(sum), true) => sum - term,
+                    });
+                }
+            }
+        }
+        return Ok(antiderivative);
+    }
+    /// Pulls constant factors out, then integrates what is left directly, by substitution or
+    /// by parts.
+    fn integrate_product(&self, product: &AST, depth: usize) -> Result<Option<AST>, Error> {
+        let (constant, factors) = split_factors(product, self.var);
+        let antiderivative = match factors.as_slice() {
+            [] => Some(self.variable()),
+            [factor] => self.integrate(factor, depth)?,
+            _ => match self.integrate_by_substitution(&factors, depth)? {
+                Some(antiderivative) => Some(antiderivative),
+                None => self.integrate_by_parts(&factors, depth)?,
+            },
+        };
+        return Ok(antiderivative.map(|antiderivative| match constant {
+            Some(constant) => constant * antiderivative,
+            None => antiderivative,
+        }));
+    }
+    /// A single power or function call whose argument is linear in the variable.
+    fn integrate_factor(&self, factor: &AST) -> Result<Option<AST>, Error> {
+        match factor {
+            AST::Variable { .. } => {
+                return Ok(Some(
+                    self.variable().pow(AST::Literal { value: 2. }) / AST::Literal { value: 2. },
+                ));
+            }
+            AST::BinOp {
+                op: BinOp::Pow,
+                left,
+                right,
+            } => {
+                let (base, exponent): (&AST, &AST) = (left, right);
+                if !exponent.contains_variable(self.var) {
+                    // (ax + b)^n -> (ax + b)^(n + 1) / (a (n + 1)), or ln|ax + b| / a for n = -1.
+                    let slope = match self.slope(base)? {
+                        Some(slope) => slope,
+                        None => return Ok(None),
+                    };
+                    if let AST::Literal { value } = exponent {
+                        if *value == -1. {
+                            return Ok(Some(call("ln", call("abs", base.clone())) / slope));
+                        }
+                    }
+                    let raised = exponent.clone() + AST::Literal { value: 1. };
+                    return Ok(Some(base.clone().pow(raised.clone()) / (slope * raised)));
+                }
+                if !base.contains_variable(self.var) {
+                    // c^(ax + b) -> c^(ax + b) / (a ln(c))
+                    let slope = match self.slope(exponent)? {
+                        Some(slope) => slope,
+                        None => return Ok(None),
+                    };
+                    let scale = match base {
+                        AST::SymbolicConstant { name } if name == "e" => slope,
+                        _ => slope * call("ln", base.clone()),
+                    };
+                    return Ok(Some(factor.clone() / scale));
+                }
+                return Ok(None);
+            }
+            AST::Function { name, args } if args.len() == 1 => {
+                let antiderivative = self
+                    .registry
+                    .get(name)
+                    .and_then(|definition| definition.antiderivative_at(&args[0]));
+                return match antiderivative {
+                    // f(ax + b) -> F(ax + b) / a
+                    Some(antiderivative) => {
+                        Ok(self.slope(&args[0])?.map(|slope| antiderivative / slope))
+                    }
+                    None => Ok(None),
+                };
+            }
+            _ => return Ok(None),
+        }
+    }
+    /// Looks for a factor `f(g(x))` such that the remaining factors are a constant multiple
+    /// of `g'(x)`, and if found integrates `f(u)` instead and substitutes `u = g(x)` back.
+    fn integrate_by_substitution(
+        &self,
+        factors: &[AST],
+        depth: usize,
+    ) -> Result<Option<AST>, Error> {
+        let u = self.fresh_variable(factors);
+        for (index, factor) in factors.iter().enumerate() {
+            let rest = product(factors, Some(index));
+            for (inner, outer) in substitution_candidates(factor, self.var, &u) {
+                let mut inner_derivative = inner.differentiate_wrt_with(self.var, self.registry)?;
+                inner_derivative.simplify_with(self.registry)?;
+                if let AST::Literal { value: 0. } = inner_derivative {
+                    continue;
+                }
+                let mut ratio = rest.clone() / inner_derivative;
+                ratio.simplify_with(self.registry)?;
+                if ratio.contains_variable(self.var) {
+                    continue;
+                }
+                let substituted = Integrator {
+                    var: &u,
+                    registry: self.registry,
+                };
+                if let Some(antiderivative) = substituted.integrate(&outer, depth)? {
+                    return Ok(Some(ratio * antiderivative.substitute(&u, &inner)));
+                }
+            }
+        }
+        return Ok(None);
+    }
+    /// `∫ u dv = u v - ∫ v du`, choosing `u` by the LIATE order: logarithms, inverse
+    /// trigonometric functions, algebraic, trigonometric, then exponential factors.
+    fn integrate_by_parts(&self, factors: &[AST], depth: usize) -> Result<Option<AST>, Error> {
+        if depth >= MAX_PARTS_DEPTH {
+            return Ok(None);
+        }
+        let index = match factors
+            .iter()
+            .enumerate()
+            .filter_map(|(index, factor)| liate_rank(factor, self.var).map(|rank| (rank, index)))
+            .min()
+        {
+            Some((_, index)) => index,
+            None => return Ok(None),
+        };
+        let u = &factors[index];
+        let mut v = match self.integrate(&product(factors, Some(index)), depth + 1)? {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+        v.simplify_with(self.registry)?;
+        let mut v_du = v.clone() * u.differentiate_wrt_with(self.var, self.registry)?;
+        v_du.simplify_with(self.registry)?;
+        return Ok(self
+            .integrate(&v_du, depth + 1)?
+            .map(|rest| u.clone() * v - rest));
+    }
+    /// The derivative of `ast` if it is a nonzero constant, i.e. if `ast` is linear.
+    fn slope(&self, ast: &AST) -> Result<Option<AST>, Error> {
+        let mut slope = ast.differentiate_wrt_with(self.var, self.registry)?;
+        slope.simplify_with(self.registry)?;
+        return Ok(match slope {
+            AST::Literal { value: 0. } => None,
+            _ if slope.contains_variable(self.var) => None,
+            _ => Some(slope),
+        });
+    }
+    fn variable(&self) -> AST {
+        return AST::Variable {
+            name: self.var.to_string(),
+        };
+    }
+    /// A variable name for substitution that does not already occur in `factors`.
+    fn fresh_variable(&self, factors: &[AST]) -> String {
+        let used: Vec<String> = factors.iter().flat_map(AST::variables).collect();
+        return (0..)
+            .map(|index| match index {
+                0 => "u".to_string(),
+                _ => format!("u{}", index),
+            })
+            .find(|name| name != self.var && !used.contains(name))
+            .unwrap();
+    }
+}
+
+/// Flattens a `*`/`/` chain into the product of its constant factors, if any, and its
+/// factors that depend on `var`. Divisors become negative powers.
+fn split_factors(product: &AST, var: &str) -> (Option<AST>, Vec<AST>) {
+    let mut constants = Vec::new();
+    let mut factors = Vec::new();
+    let mut pending = vec![(product, false)];
+    while let Some((ast, inverted)) = pending.pop() {
+        match ast {
+            AST::BinOp {
+                op: BinOp::Mul,
+                left,
+                right,
+            } => {
+                pending.push((right, inverted));
+                pending.push((left, inverted));
+            }
+            AST::BinOp {
+                op: BinOp::Div,
+                left,
+                right,
+            } => {
+                pending.push((right, !inverted));
+                pending.push((left, inverted));
+            }
+            AST::Function { name, args } if name == "-" && args.len() == 1 => {
+                constants.push(AST::Literal { value: -1. });
+                pending.push((&args[0], inverted));
+            }
+            _ => {
+                let factor = if inverted {
+                    invert(ast.clone(), negate)
+                } else {
+                    ast.clone()
+                };
+                if ast.contains_variable(var) {
+                    factors.push(factor);
+                } else {
+                    constants.push(factor);
+                }
+            }
+        }
+    }
+    return (
+        constants
+            .into_iter()
+            .reduce(|product, factor| product * factor),
+        factors,
+    );
+}
+
+/// Ways of reading `factor` as `f(g)`: each candidate is `g` together with `f(u)`.
+fn substitution_candidates(factor: &AST, var: &str, u: &str) -> Vec<(AST, AST)> {
+    let u = AST::Variable {
+        name: u.to_string(),
+    };
+    let mut candidates = Vec::new();
+    match factor {
+        AST::Function { name, args } if args.len() == 1 && args[0].contains_variable(var) => {
+            candidates.push(((*args[0]).clone(), call(name, u.clone())));
+        }
+        AST::BinOp {
+            op: BinOp::Pow,
+            left,
+            right,
+        } => {
+            if !right.contains_variable(var) {
+                candidates.push(((**left).clone(), u.clone().pow(right.clone())));
+            } else if !left.contains_variable(var) {
+                candidates.push(((**right).clone(), (**left).clone().pow(u.clone())));
+            }
+        }
+        _ => (),
+    }
+    // The factor itself as `g`, e.g. `sin(x)` in `sin(x) * cos(x)`.
+    candidates.push((factor.clone(), u));
+    return candidates;
+}
+
+/// Position in the LIATE order, or `None` for factors that are not a good choice for `u`.
+fn liate_rank(factor: &AST, var: &str) -> Option<u8> {
+    return match factor {
+        AST::Function { name, .. } => match name.as_str() {
+            "ln" | "log10" | "log2" => Some(0),
+            "asin" | "acos" | "atan" => Some(1),
+            "sin" | "cos" | "sinh" | "cosh" => Some(3),
+            "exp" => Some(4),
+            _ => None,
+        },
+        AST::Variable { name } if name == var => Some(2),
+        AST::BinOp {
+            op: BinOp::Pow,
+            left,
+            right,
+        } => match (left.contains_variable(var), right.contains_variable(var)) {
+            (true, false) => Some(2),
+            (false, true) => Some(4),
+            _ => None,
+        },
+        _ => None,
+    };
+}
+
+/// Product of `factors`, leaving out the one at `skip`.
+fn product(factors: &[AST], skip: Option<usize>) -> AST {
+    return factors
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| Some(*index) != skip)
+        .map(|(_, factor)| factor.clone())
+        .reduce(|product, factor| product * factor)
+        .unwrap_or(AST::Literal { value: 1. });
+}
+
+/// Coefficients of `ast` as a polynomial in `var` with numeric coefficients, lowest power
+/// first, or `None` if it is not one.
+pub(crate) fn as_polynomial(ast: &AST, var: &str) -> Option<Vec<f64>> {
+    return ast
+        .try_fold(|node, children: Vec<Vec<f64>>| {
+            let mut children = children.into_iter();
+            return match node {
+                AST::Literal { value } => Ok(vec![*value]),
+                AST::Variable { name } if name == var => Ok(vec![0., 1.]),
+                AST::Function { name, args } if name == "-" && args.len() == 1 => {
+                    Ok(children.next().unwrap().iter().map(|c| -c).collect())
+                }
+                AST::BinOp { op, right, .. } => {
+                    let left = children.next().unwrap();
+                    let right_coefficients = children.next().unwrap();
+                    match (op, &**right) {
+                        (BinOp::Pow, AST::Literal { value }) => {
+                            if value.fract() != 0.
+                                || *value < 0.
+                                || (left.len() - 1) as f64 * value > MAX_POLYNOMIAL_DEGREE as f64
+                            {
+                                return Err(());
+                            }
+                            Ok((0..*value as usize)
+                                .fold(vec![1.], |power, _| multiply_polynomials(&power, &left)))
+                        }
+                        (BinOp::Div, AST::Literal { value }) if *value != 0. => {
+                            Ok(left.iter().map(|coefficient| coefficient / value).collect())
+                        }
+                        (BinOp::Pow | BinOp::Div, _) => Err(()),
+                        (BinOp::Add, _) => Ok(add_polynomials(&left, &right_coefficients, false)),
+                        (BinOp::Sub, _) => Ok(add_polynomials(&left, &right_coefficients, true)),
+                        (BinOp::Mul, _) => {
+                            if left.len() + right_coefficients.len() - 2 > MAX_POLYNOMIAL_DEGREE {
+                                return Err(());
+                            }
+                            Ok(multiply_polynomials(&left, &right_coefficients))
+                        }
+                    }
+                }
+                _ => Err(()),
+            };
+        })
+        .ok();
+}
+
+fn integrate_polynomial(coefficients: &[f64], var: AST) -> AST {
+    return coefficients
+        .iter()
+        .enumerate()
+        .filter(|(_, coefficient)| **coefficient != 0.)
+        .map(|(power, coefficient)| {
+            let power = (power + 1) as f64;
+            AST::Literal {
+                value: coefficient / power,
+            } * var.clone().pow(AST::Literal { value: power })
+        })
+        .reduce(|sum, term| sum + term)
+        .unwrap_or(AST::Literal { value: 0. });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{as_polynomial, Integrator};
+    use crate::{functions::FunctionRegistry, parse_str};
+
+    fn integral(source: &str) -> String {
+        return parse_str(source)
+            .unwrap()
+            .integrate("x")
+            .unwrap()
+            .to_string();
+    }
+
+    #[test]
+    fn antiderivatives_differentiate_back() {
+        assert_eq!(integral("x * exp(x)"), "x * exp(x) - exp(x)");
+        assert_eq!(integral("2 * x * cos(x ^ 2)"), "sin(x ^ 2)");
+        assert_eq!(integral("x ^ 2 * ln(x)"), "ln(x) * x ^ 3 / 3 - x ^ 3 / 9");
+        assert_eq!(integral("1 / x"), "ln(abs(x))");
+        assert!(parse_str("exp(x) * sin(x)")
+            .unwrap()
+            .integrate("x")
+            .is_err());
+    }
+
+    #[test]
+    fn wrong_antiderivatives_are_rejected() {
+        let integrator = Integrator {
+            var: "x",
+            registry: FunctionRegistry::builtin(),
+        };
+        let integrand = parse_str("x * cos(x)").unwrap();
+        let right = parse_str("x * sin(x) + cos(x)").unwrap();
+        let wrong = parse_str("x * sin(x) - cos(x)").unwrap();
+        assert!(integrator.verify(&right, &integrand).unwrap());
+        assert!(!integrator.verify(&wrong, &integrand).unwrap());
+    }
+
+    #[test]
+    fn polynomial_coefficients() {
+        let polynomial = parse_str("(x + 1) ^ 2 - x / 2").unwrap();
+        assert_eq!(as_polynomial(&polynomial, "x"), Some(vec![1., 1.5, 1.]));
+        assert_eq!(as_polynomial(&parse_str("x ^ y").unwrap(), "x"), None);
+        assert_eq!(as_polynomial(&parse_str("1 / x").unwrap(), "x"), None);
+    }
+}
@@ -1,111 +1,327 @@
-use std::{error::Error, fmt};
+use std::{error::Error, fmt, iter::Peekable, str::CharIndices};
 
-use crate::tokens::{BinOp, Token};
+use crate::tokens::{BinOp, Span, SpannedToken, Token};
 
 #[derive(Debug)]
-pub struct LexError;
+pub enum LexError {
+    UnexpectedCharacter { character: char, span: Span },
+    MalformedNumber { span: Span },
+}
+
+impl LexError {
+    /// Where in the source the error occurred.
+    pub fn span(&self) -> Span {
+        return match self {
+            LexError::UnexpectedCharacter { span, .. } | LexError::MalformedNumber { span } => {
+                *span
+            }
+        };
+    }
+}
 
 impl Error for LexError {}
 
 impl fmt::Display for LexError {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        return write!(formatter, "rip");
+        return match self {
+            LexError::UnexpectedCharacter { character, .. } => {
+                write!(formatter, "unexpected character `{}`", character)
+            }
+            LexError::MalformedNumber { .. } => write!(formatter, "malformed number"),
+        };
     }
 }
 
-pub fn lex(src: &str) -> Result<Vec<Token>, LexError> {
-    let mut tokens: Vec<Token> = Vec::new();
-    let mut index = 0;
-    loop {
-        if let Some(current) = src.chars().nth(index) {
-            tokens.push(match current {
-                '(' => {
-                    index += 1;
-                    Token::OpenParen
-                }
-                ')' => {
-                    index += 1;
-                    Token::CloseParen
-                }
-                '-' => {
-                    index += 1;
-                    Token::BinOp(BinOp::Sub)
-                }
-                '+' => {
-                    index += 1;
-                    Token::BinOp(BinOp::Add)
-                }
-                '*' => {
-                    index += 1;
-                    Token::BinOp(BinOp::Mul)
-                }
-                '/' => {
-                    index += 1;
-                    Token::BinOp(BinOp::Div)
-                }
-                '^' => {
-                    index += 1;
-                    Token::BinOp(BinOp::Pow)
-                }
-                ',' => {
-                    index += 1;
-                    Token::Comma
-                }
-                ' ' => {
-                    index += 1;
-                    continue;
-                }
-                '0'..='9' => get_number_token_at(src, &mut index)?,
-                'a'..='z' | 'A'..='Z' => get_const_variable_or_function_token_at(src, &mut index)?,
-                _ => return Err(LexError),
-            })
+pub fn lex(src: &str) -> Result<Vec<SpannedToken>, LexError> {
+    let mut tokens: Vec<SpannedToken> = Vec::new();
+    let mut cursor = Cursor::new(src);
+    while let Some(current) = cursor.peek() {
+        let start = cursor.offset();
+        let token = if current.is_whitespace() {
+            cursor.bump();
+            continue;
+        } else if let Some(token) = get_symbol_token(current) {
+            cursor.bump();
+            token
+        } else if current.is_ascii_digit() || current == '.' {
+            get_number_token(&mut cursor)?
+        } else if is_identifier_start(current) {
+            get_const_variable_or_function_token(&mut cursor)
         } else {
-            break;
-        }
+            return Err(LexError::UnexpectedCharacter {
+                character: current,
+                span: Span {
+                    start,
+                    end: start + current.len_utf8(),
+                },
+            });
+        };
+        tokens.push(SpannedToken {
+            token,
+            span: Span {
+                start,
+                end: cursor.offset(),
+            },
+        });
     }
     return Ok(tokens);
 }
 
-fn get_const_variable_or_function_token_at(
-    source: &str,
-    index: &mut usize,
-) -> Result<Token, LexError> {
-    let start = *index;
-    for current in source.get(start..).unwrap().chars() {
-        match current {
-            'a'..='z' | 'A'..='Z' => {
-                *index += 1;
-            }
-            _ => break,
+/// A single pass over the source, tracking byte offsets.
+struct Cursor<'a> {
+    source: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(source: &'a str) -> Self {
+        return Cursor {
+            source,
+            chars: source.char_indices().peekable(),
+        };
+    }
+    fn peek(&mut self) -> Option<char> {
+        return self.chars.peek().map(|(_, character)| *character);
+    }
+    /// Looks `n` characters past the next one without consuming anything.
+    fn peek_nth(&self, n: usize) -> Option<char> {
+        return self.chars.clone().nth(n).map(|(_, character)| character);
+    }
+    fn bump(&mut self) -> Option<char> {
+        return self.chars.next().map(|(_, character)| character);
+    }
+    fn bump_while(&mut self, predicate: impl Fn(char) -> bool) {
+        while self.peek().is_some_and(&predicate) {
+            self.bump();
         }
     }
-    let identifier = source.get(start..*index).unwrap().to_string();
-    return match source.chars().nth(*index) {
-        Some('(') => Ok(Token::Function(identifier)),
+    /// Byte offset of the next character.
+    fn offset(&mut self) -> usize {
+        let end = self.source.len();
+        return self.chars.peek().map_or(end, |(offset, _)| *offset);
+    }
+    fn slice_from(&mut self, start: usize) -> &'a str {
+        let end = self.offset();
+        return &self.source[start..end];
+    }
+}
+
+fn get_symbol_token(character: char) -> Option<Token> {
+    return Some(match character {
+        '(' => Token::OpenParen,
+        ')' => Token::CloseParen,
+        '-' | '−' => Token::BinOp(BinOp::Sub),
+        '+' => Token::BinOp(BinOp::Add),
+        '*' | '×' | '·' => Token::BinOp(BinOp::Mul),
+        '/' | '÷' => Token::BinOp(BinOp::Div),
+        '^' => Token::BinOp(BinOp::Pow),
+        ',' => Token::Comma,
+        '√' => Token::Radical,
+        '=' => Token::Equals,
+        'π' => Token::SymbolicConstant("pi".to_string()),
+        _ => return None,
+    });
+}
+
+fn get_const_variable_or_function_token(cursor: &mut Cursor) -> Token {
+    let start = cursor.offset();
+    cursor.bump();
+    cursor.bump_while(is_identifier_continue);
+    let identifier = cursor.slice_from(start).to_string();
+    return match cursor.peek() {
+        Some('(') => Token::Function(identifier),
         _ => match identifier.as_str() {
-            "e" | "pi" => Ok(Token::SymbolicConstant(identifier)),
-            _ => Ok(Token::Variable(identifier)),
+            "e" | "pi" => Token::SymbolicConstant(identifier),
+            "inf" => Token::Literal(f64::INFINITY),
+            "nan" => Token::Literal(f64::NAN),
+            _ => Token::Variable(identifier),
+        },
+    };
+}
+
+/// Identifiers start with an ASCII letter, `_` or a Greek letter, and may continue with
+/// digits and primes as well, e.g. `x1`, `theta_0`, `k_B`, `α` or `f'`. `π` is not a letter
+/// here but a constant of its own, so `πr` reads as `π` followed by `r`.
+fn is_identifier_start(character: char) -> bool {
+    return character.is_ascii_alphabetic() || character == '_' || is_greek(character);
+}
+
+fn is_identifier_continue(character: char) -> bool {
+    return is_identifier_start(character) || character.is_ascii_digit() || character == '\'';
+}
+
+fn is_greek(character: char) -> bool {
+    return character != 'π'
+        && matches!(character, 'Α'..='Ω' | 'α'..='ω' | 'ϑ' | 'ϕ' | 'ϖ' | 'ϰ' | 'ϱ' | 'ϵ');
+}
+
+/// Reads a numeric literal: a decimal with optional fraction and exponent (`6.022E23`, `.5`,
+/// `1e-6`) or a hexadecimal integer (`0x1F`). Digits may be grouped with underscores (`1_000`).
+fn get_number_token(cursor: &mut Cursor) -> Result<Token, LexError> {
+    let start = cursor.offset();
+    let malformed = |cursor: &mut Cursor| LexError::MalformedNumber {
+        span: Span {
+            start,
+            end: cursor.offset().max(start + 1),
         },
     };
+    if cursor.peek() == Some('0') && matches!(cursor.peek_nth(1), Some('x' | 'X')) {
+        cursor.bump();
+        cursor.bump();
+        let digits_start = cursor.offset();
+        bump_digits(cursor, start, |character| character.is_ascii_hexdigit())?;
+        let digits = cursor.slice_from(digits_start);
+        if digits.is_empty() {
+            return Err(malformed(cursor));
+        }
+        let value = digits
+            .chars()
+            .filter_map(|digit| digit.to_digit(16))
+            .fold(0., |value, digit| value * 16. + digit as f64);
+        return Ok(Token::Literal(value));
+    }
+    bump_digits(cursor, start, |character| character.is_ascii_digit())?;
+    if cursor.peek() == Some('.') {
+        cursor.bump();
+        bump_digits(cursor, start, |character| character.is_ascii_digit())?;
+        if cursor.peek() == Some('.') {
+            cursor.bump();
+            return Err(malformed(cursor));
+        }
+    }
+    // Only treat `e` as an exponent if digits follow, so `2e` is still `2` and `e`.
+    if let Some('e' | 'E') = cursor.peek() {
+        let digits_at = match cursor.peek_nth(1) {
+            Some('+' | '-') => 2,
+            _ => 1,
+        };
+        if cursor
+            .peek_nth(digits_at)
+            .is_some_and(|character| character.is_ascii_digit())
+        {
+            for _ in 0..digits_at {
+                cursor.bump();
+            }
+            bump_digits(cursor, start, |character| character.is_ascii_digit())?;
+        }
+    }
+    let text = cursor.slice_from(start).replace('_', "");
+    return match str::parse::<f64>(&text) {
+        Ok(value) => Ok(Token::Literal(value)),
+        Err(_) => Err(malformed(cursor)),
+    };
 }
 
-fn get_number_token_at(source: &str, index: &mut usize) -> Result<Token, LexError> {
-    let mut seen_dot = false;
-    let start = *index;
-    for current in source.get(start..).unwrap().chars() {
-        match current {
-            '0'..='9' => {}
-            '.' => {
-                if seen_dot {
-                    return Err(LexError);
-                }
-                seen_dot = true;
+/// Consumes a run of digits. Underscores are allowed between digits; a misplaced one is an
+/// error spanning from the start of the number at `start` through the underscore.
+fn bump_digits(
+    cursor: &mut Cursor,
+    start: usize,
+    is_digit: fn(char) -> bool,
+) -> Result<(), LexError> {
+    let mut after_digit = false;
+    while let Some(current) = cursor.peek() {
+        if is_digit(current) {
+            after_digit = true;
+        } else if current == '_' {
+            if !after_digit || !cursor.peek_nth(1).is_some_and(is_digit) {
+                cursor.bump();
+                return Err(LexError::MalformedNumber {
+                    span: Span {
+                        start,
+                        end: cursor.offset(),
+                    },
+                });
             }
-            _ => break,
+        } else {
+            break;
+        }
+        cursor.bump();
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lex, LexError};
+    use crate::tokens::Span;
+
+    fn tokens(src: &str) -> Vec<String> {
+        return lex(src)
+            .unwrap()
+            .iter()
+            .map(|spanned| format!("{:?}", spanned.token))
+            .collect();
+    }
+
+    #[test]
+    fn numeric_literals() {
+        assert_eq!(tokens("0x1F"), ["Literal(31.0)"]);
+        assert_eq!(tokens("1_000"), ["Literal(1000.0)"]);
+        assert_eq!(tokens(".5"), ["Literal(0.5)"]);
+        assert_eq!(tokens("6.022E23"), ["Literal(6.022e23)"]);
+        assert_eq!(tokens("1e-6"), ["Literal(1e-6)"]);
+        assert_eq!(tokens("2e"), ["Literal(2.0)", "SymbolicConstant(\"e\")"]);
+    }
+
+    #[test]
+    fn unicode_and_greek_identifiers() {
+        assert_eq!(tokens("theta_0"), ["Variable(\"theta_0\")"]);
+        assert_eq!(tokens("k_B"), ["Variable(\"k_B\")"]);
+        assert_eq!(tokens("α2"), ["Variable(\"α2\")"]);
+        assert_eq!(tokens("f'"), ["Variable(\"f'\")"]);
+        assert_eq!(tokens("ω(t)")[0], "Function(\"ω\")");
+        assert_eq!(
+            tokens("πr"),
+            ["SymbolicConstant(\"pi\")", "Variable(\"r\")"]
+        );
+        assert_eq!(
+            tokens("rπ"),
+            ["Variable(\"r\")", "SymbolicConstant(\"pi\")"]
+        );
+        assert!(matches!(
+            lex("x ∑ y"),
+            Err(LexError::UnexpectedCharacter {
+                character: '∑', ..
+            })
+        ));
+    }
+
+    #[test]
+    fn spans_are_byte_offsets() {
+        let spans: Vec<(usize, usize)> = lex("α + 1_0 ×  sin(x)")
+            .unwrap()
+            .iter()
+            .map(|spanned| (spanned.span.start, spanned.span.end))
+            .collect();
+        assert_eq!(
+            spans,
+            [
+                (0, 2),
+                (3, 4),
+                (5, 8),
+                (9, 11),
+                (13, 16),
+                (16, 17),
+                (17, 18),
+                (18, 19)
+            ]
+        );
+        assert_eq!(lex("1 + β$").unwrap_err().span(), Span { start: 6, end: 7 });
+        assert_eq!(
+            lex("2 * 1.2.3").unwrap_err().span(),
+            Span { start: 4, end: 8 }
+        );
+    }
+
+    #[test]
+    fn malformed_numbers() {
+        for src in [".", "1__0", "1_", "1.2.3", "0x"] {
+            assert!(
+                matches!(lex(src), Err(LexError::MalformedNumber { .. })),
+                "{}",
+                src
+            );
         }
-        *index += 1;
     }
-    return Ok(Token::Literal(
-        str::parse::<f64>(source.get(start..*index).unwrap()).unwrap(),
-    ));
 }
@@ -0,0 +1,247 @@
+//! A mathematical expression parser and symbolic differentiator.
+//!
+//! The usual entry point is [`differentiate_str`], which lexes, parses, simplifies and
+//! differentiates an expression in one go. The individual stages ([`lex`], [`parse`],
+//! [`AST::simplify`] and [`AST::differentiate`]) are exposed as well for callers that
+//! need finer control, and [`AST::evaluate`] computes numeric values.
+#![allow(
+    clippy::needless_return,
+    clippy::upper_case_acronyms,
+    clippy::single_match,
+    clippy::collapsible_match
+)]
+
+mod analysis;
+mod ast;
+mod builtins;
+mod canonical;
+mod collector;
+mod dag;
+mod diagnostics;
+mod differentiator;
+mod equation;
+mod evaluator;
+mod functions;
+mod integrator;
+mod lexer;
+mod limits;
+mod multivariate;
+mod parser;
+mod polynomial;
+mod quadrature;
+mod roots;
+mod series;
+mod simplifier;
+mod table;
+mod tokens;
+
+use std::{error, fmt};
+
+pub use analysis::{
+    analyze, analyze_with, render_analysis, Analysis, AnalysisError, CriticalPoint,
+    MonotonicInterval, PointKind, Trend,
+};
+pub use ast::AST;
+pub use diagnostics::render_error;
+pub use differentiator::DifferentiationError;
+pub use equation::{solve, solve_with, Equation, SolveError};
+pub use functions::{
+    AntiderivativeFn, DerivativeFn, FunctionDefinition, FunctionRegistry, NumericFn, SpecialValueFn,
+};
+pub use integrator::IntegrationError;
+pub use lexer::{lex, LexError};
+pub use limits::{limit, limit_with, Direction, LimitError};
+pub use multivariate::{
+    gradient, gradient_with, hessian, hessian_with, jacobian, jacobian_with, render_matrix,
+};
+pub use parser::{
+    parse, parse_equation, parse_equation_with, parse_with, ParseError, ParseOptions,
+};
+pub use quadrature::{integrate_definite, integrate_definite_with, DefiniteIntegral};
+pub use roots::{
+    find_root, find_root_system, find_root_system_with, find_root_with, RootError, RootOptions,
+};
+pub use series::{
+    taylor, taylor_remainder_bound, taylor_remainder_bound_with, taylor_with, SeriesError,
+};
+pub use simplifier::EvaluationError;
+pub use tokens::{BinOp, Span, SpannedToken, Token};
+
+/// Any error produced while processing an expression from source text.
+#[derive(Debug)]
+pub enum Error {
+    Lex(LexError),
+    Parse(ParseError),
+    Evaluation(EvaluationError),
+    Differentiation(DifferentiationError),
+    Integration(IntegrationError),
+    Series(SeriesError),
+    Limit(LimitError),
+    Root(RootError),
+    Solve(SolveError),
+    Analysis(AnalysisError),
+}
+
+impl Error {
+    /// Where in the source the error occurred, for lex and parse errors.
+    pub fn span(&self) -> Option<Span> {
+        return match self {
+            Error::Lex(err) => Some(err.span()),
+            Error::Parse(err) => Some(err.span()),
+            Error::Evaluation(_)
+            | Error::Differentiation(_)
+            | Error::Integration(_)
+            | Error::Series(_)
+            | Error::Limit(_)
+            | Error::Root(_)
+            | Error::Solve(_)
+            | Error::Analysis(_) => None,
+        };
+    }
+    /// Formats the error, pointing at its location in `source` when it has one.
+    pub fn render(&self, source: &str) -> String {
+        return match self.span() {
+            Some(span) => render_error(source, span, &self.to_string()),
+            None => self.to_string(),
+        };
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        return match self {
+            Error::Lex(err) => Some(err),
+            Error::Parse(err) => Some(err),
+            Error::Evaluation(err) => Some(err),
+            Error::Differentiation(err) => Some(err),
+            Error::Integration(err) => Some(err),
+            Error::Series(err) => Some(err),
+            Error::Limit(err) => Some(err),
+            Error::Root(err) => Some(err),
+            Error::Solve(err) => Some(err),
+            Error::Analysis(err) => Some(err),
+        };
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            Error::Lex(err) => write!(formatter, "lex error: {}", err),
+            Error::Parse(err) => write!(formatter, "parse error: {}", err),
+            Error::Evaluation(err) => write!(formatter, "evaluation error: {}", err),
+            Error::Differentiation(err) => write!(formatter, "differentiation error: {}", err),
+            Error::Integration(err) => write!(formatter, "integration error: {}", err),
+            Error::Series(err) => write!(formatter, "series error: {}", err),
+            Error::Limit(err) => write!(formatter, "limit error: {}", err),
+            Error::Root(err) => write!(formatter, "root finding error: {}", err),
+            Error::Solve(err) => write!(formatter, "solve error: {}", err),
+            Error::Analysis(err) => write!(formatter, "analysis error: {}", err),
+        };
+    }
+}
+
+impl From<LexError> for Error {
+    fn from(err: LexError) -> Self {
+        return Error::Lex(err);
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(err: ParseError) -> Self {
+        return Error::Parse(err);
+    }
+}
+
+impl From<EvaluationError> for Error {
+    fn from(err: EvaluationError) -> Self {
+        return Error::Evaluation(err);
+    }
+}
+
+impl From<DifferentiationError> for Error {
+    fn from(err: DifferentiationError) -> Self {
+        return Error::Differentiation(err);
+    }
+}
+
+impl From<IntegrationError> for Error {
+    fn from(err: IntegrationError) -> Self {
+        return Error::Integration(err);
+    }
+}
+
+impl From<SeriesError> for Error {
+    fn from(err: SeriesError) -> Self {
+        return Error::Series(err);
+    }
+}
+
+impl From<LimitError> for Error {
+    fn from(err: LimitError) -> Self {
+        return Error::Limit(err);
+    }
+}
+
+impl From<RootError> for Error {
+    fn from(err: RootError) -> Self {
+        return Error::Root(err);
+    }
+}
+
+impl From<SolveError> for Error {
+    fn from(err: SolveError) -> Self {
+        return Error::Solve(err);
+    }
+}
+
+impl From<AnalysisError> for Error {
+    fn from(err: AnalysisError) -> Self {
+        return Error::Analysis(err);
+    }
+}
+
+/// Lexes and parses `src` into an [`AST`] without simplifying it.
+pub fn parse_str(src: &str) -> Result<AST, Error> {
+    let tokens = lex(src)?;
+    return Ok(parse(&tokens)?);
+}
+
+/// Lexes and parses `src` into an [`Equation`] such as `x^2 - 3x = 4`.
+pub fn parse_equation_str(src: &str) -> Result<Equation, Error> {
+    let tokens = lex(src)?;
+    return Ok(parse_equation(&tokens)?);
+}
+
+/// Parses `src` and returns its simplified derivative with respect to its only variable.
+pub fn differentiate_str(src: &str) -> Result<AST, Error> {
+    let mut ast = parse_str(src)?;
+    // Chosen before simplifying, which may cancel the variable, as in `x - x`.
+    let var = match ast.only_variable()? {
+        Some(var) => var,
+        None => return Ok(AST::Literal { value: 0. }),
+    };
+    ast.simplify()?;
+    let mut derivative = ast.differentiate_wrt(&var)?;
+    derivative.simplify()?;
+    return Ok(derivative);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{differentiate_str, parse_str};
+
+    #[test]
+    fn differentiates_strings() {
+        assert_eq!(differentiate_str("x^3").unwrap().to_string(), "3 * x ^ 2");
+        assert_eq!(differentiate_str("x - x").unwrap().to_string(), "0");
+        assert!(differentiate_str("x * y").is_err());
+    }
+
+    #[test]
+    fn parse_errors_point_at_the_source() {
+        let err = parse_str("x + * 2").unwrap_err();
+        assert!(err.span().is_some());
+        assert!(err.render("x + * 2").starts_with("x + * 2\n    ^ "));
+    }
+}
@@ -0,0 +1,710 @@
+use std::{collections::HashMap, error, fmt};
+
+use crate::{
+    ast::{call, literal, AST, ZERO_TOLERANCE},
+    functions::FunctionRegistry,
+    integrator::as_polynomial,
+    tokens::BinOp,
+    Error,
+};
+
+#[derive(Debug)]
+pub enum LimitError {
+    /// The one-sided limits differ, or the expression oscillates.
+    DoesNotExist { expr: String, approach: String },
+    /// None of the rules could resolve an indeterminate form.
+    Indeterminate { expr: String, approach: String },
+}
+impl error::Error for LimitError {}
+
+impl fmt::Display for LimitError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            LimitError::DoesNotExist { expr, approach } => write!(
+                formatter,
+                "the limit of `{}` as {} does not exist",
+                expr, approach
+            ),
+            LimitError::Indeterminate { expr, approach } => write!(
+                formatter,
+                "could not determine the limit of `{}` as {}",
+                expr, approach
+            ),
+        };
+    }
+}
+
+/// Which side a finite point is approached from. Ignored for limits at `±inf`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Both,
+    /// From below, `x → a⁻`.
+    Left,
+    /// From above, `x → a⁺`.
+    Right,
+}
+
+/// How many times L'Hôpital's rule may be applied to one quotient.
+const MAX_LHOPITAL_STEPS: usize = 10;
+/// Values at least this large are taken to be a pole that rounding moved off the point, as
+/// with `tan(pi / 2)`.
+const POLE_MAGNITUDE: f64 = 1e15;
+/// Offsets from a finite point, and magnitudes for an infinite one, at which an expression is
+/// sampled to tell which way it diverges.
+const NEAR_OFFSETS: [f64; 3] = [1e-3, 1e-5, 1e-7];
+const FAR_POINTS: [f64; 3] = [1e1, 1e2, 1e3];
+
+/// The limit of `expr` as `var` approaches `point`, which may be symbolic or `±inf`. Infinite
+/// limits are returned as the literal `inf` or `-inf`.
+///
+/// The point is substituted directly when that gives a defined value, which assumes `expr` is
+/// continuous there. Otherwise the limit is taken piece by piece: `0/0` and `∞/∞` quotients
+/// are resolved with L'Hôpital's rule, `0·∞` products are rewritten as quotients, and `1^∞`,
+/// `0^0` and `∞^0` powers go through `exp(g ln(f))`. Rational functions at `±inf` are settled
+/// by comparing degrees. Whether something tends to `+inf` or `-inf` is decided by sampling
+/// it close to the point.
+pub fn limit(expr: &AST, var: &str, point: &AST, direction: Direction) -> Result<AST, Error> {
+    return limit_with(expr, var, point, direction, FunctionRegistry::builtin());
+}
+
+/// [`limit`] using the functions in `registry`.
+pub fn limit_with(
+    expr: &AST,
+    var: &str,
+    point: &AST,
+    direction: Direction,
+    registry: &FunctionRegistry,
+) -> Result<AST, Error> {
+    let numeric_point = point.evaluate_with(&HashMap::new(), registry).ok();
+    let solver = |direction| Solver {
+        var,
+        point,
+        infinity: numeric_point.filter(|value| value.is_infinite()),
+        numeric_point,
+        direction,
+        registry,
+    };
+    let mut simplified = expr.clone();
+    simplified.simplify_with(registry)?;
+    // A limit that does not exist is reported for the whole expression, not for the
+    // subexpression where that was found, such as `1 / x` in `sin(1 / x)`.
+    let limit = match resolve(&simplified, &solver, direction) {
+        Err(Error::Limit(LimitError::DoesNotExist { .. })) => {
+            return Err(LimitError::DoesNotExist {
+                expr: expr.to_string(),
+                approach: solver(direction).approach(),
+            }
+            .into())
+        }
+        limit => limit?,
+    };
+    return Ok(match limit {
+        Limit::Finite(value) => value,
+        Limit::Infinite(sign) => literal(infinity_value(sign)),
+    });
+}
+
+/// The limit of the simplified `expr` from `direction`, taking both one-sided limits and
+/// checking that they agree when approaching from both sides.
+fn resolve<'a>(
+    expr: &AST,
+    solver: &impl Fn(Direction) -> Solver<'a>,
+    direction: Direction,
+) -> Result<Limit, Error> {
+    return match direction {
+        Direction::Both => {
+            let left = solver(Direction::Left).one_sided(expr)?;
+            let right = solver(Direction::Right).one_sided(expr)?;
+            if left == *expr && right == *expr {
+                solver(Direction::Both).limit(expr, 0)
+            } else {
+                let left = solver(Direction::Left).limit(&left, 0)?;
+                let right = solver(Direction::Right).limit(&right, 0)?;
+                solver(Direction::Both).agree(expr, left, right)
+            }
+        }
+        _ => {
+            let solver = solver(direction);
+            solver.limit(&solver.one_sided(expr)?, 0)
+        }
+    };
+}
+
+#[derive(Debug)]
+enum Limit {
+    Finite(AST),
+    /// `+inf` or `-inf`, by the sign of the value.
+    Infinite(f64),
+}
+
+struct Solver<'a> {
+    var: &'a str,
+    point: &'a AST,
+    numeric_point: Option<f64>,
+    /// `Some(±inf)` when the point is infinite.
+    infinity: Option<f64>,
+    direction: Direction,
+    registry: &'a FunctionRegistry,
+}
+
+impl Solver<'_> {
+    /// Rewrites the functions with a jump or kink where their argument tends to zero, such as
+    /// `abs` and `sign`, into what they equal on the side of a finite point being approached,
+    /// so that substituting the point and L'Hôpital's rule see a smooth expression. The side
+    /// an argument lies on is found by sampling it.
+    fn one_sided(&self, expr: &AST) -> Result<AST, Error> {
+        let side = match self.direction {
+            Direction::Left => -1.,
+            Direction::Right => 1.,
+            Direction::Both => return Ok(expr.clone()),
+        };
+        let point = match self.numeric_point {
+            Some(point) if point.is_finite() => point,
+            _ => return Ok(expr.clone()),
+        };
+        let mut rewritten = expr.fold(|node, children: Vec<AST>| {
+            let node = node.with_children(children);
+            let (name, arg) = match &node {
+                AST::Function { name, args } if args.len() == 1 && is_kinked(name) => {
+                    (name.as_str(), &*args[0])
+                }
+                _ => return node,
+            };
+            let side_of_zero = match self.side_of_zero(arg, point, side) {
+                Some(sign) => sign,
+                None => return node,
+            };
+            return match (name, side_of_zero) {
+                ("abs", sign) if sign > 0. => arg.clone(),
+                ("abs", sign) if sign < 0. => literal(-1.) * arg.clone(),
+                ("abs", _) => literal(0.),
+                ("sign", sign) => literal(sign),
+                (_, sign) => literal((1. + sign) / 2.),
+            };
+        });
+        rewritten.simplify_with(self.registry)?;
+        return Ok(rewritten);
+    }
+    /// When `arg` tends to zero at `point`, the sign it has just to the `side` of it, or `0`
+    /// if it stays zero there; `None` if it tends elsewhere or its sign is not consistent.
+    fn side_of_zero(&self, arg: &AST, point: f64, side: f64) -> Option<f64> {
+        let mut env = HashMap::from([(self.var.to_string(), point)]);
+        let at_point = arg.evaluate_with(&env, self.registry).ok()?;
+        if at_point.abs() >= ZERO_TOLERANCE {
+            return None;
+        }
+        let mut signs = Vec::new();
+        for offset in NEAR_OFFSETS {
+            env.insert(
+                self.var.to_string(),
+                point + side * offset * point.abs().max(1.),
+            );
+            let value = arg.evaluate_with(&env, self.registry).ok()?;
+            signs.push(if value == 0. { 0. } else { value.signum() });
+        }
+        if signs.iter().any(|sign| *sign != signs[0]) {
+            return None;
+        }
+        return Some(signs[0]);
+    }
+    /// The two-sided limit from the one-sided ones, which must be equal.
+    fn agree(&self, expr: &AST, left: Limit, right: Limit) -> Result<Limit, Error> {
+        let equal = match (&left, &right) {
+            (Limit::Infinite(a), Limit::Infinite(b)) => a == b,
+            (Limit::Finite(a), Limit::Finite(b)) => {
+                match (self.value_of(&left), self.value_of(&right)) {
+                    (Some(x), Some(y)) => (x - y).abs() <= ZERO_TOLERANCE * (1. + x.abs()),
+                    _ => a == b,
+                }
+            }
+            _ => false,
+        };
+        if !equal {
+            return Err(LimitError::DoesNotExist {
+                expr: expr.to_string(),
+                approach: self.approach(),
+            }
+            .into());
+        }
+        return Ok(left);
+    }
+    fn limit(&self, expr: &AST, depth: usize) -> Result<Limit, Error> {
+        if !expr.contains_variable(self.var) {
+            return Ok(Limit::Finite(expr.clone()));
+        }
+        match self.infinity {
+            None => {
+                if let Some(value) = self.defined(expr.substitute(self.var, self.point)) {
+                    return Ok(Limit::Finite(value));
+                }
+            }
+            Some(infinity) => {
+                if let Some(limit) = self.rational_at_infinity(expr, infinity) {
+                    return Ok(limit);
+                }
+            }
+        }
+        return match expr {
+            AST::Variable { .. } => match self.infinity {
+                Some(infinity) => Ok(Limit::Infinite(infinity)),
+                None => Ok(Limit::Finite(self.point.clone())),
+            },
+            AST::BinOp { op, left, right } => match op {
+                BinOp::Add => self.sum(expr, left, right, 1., depth),
+                BinOp::Sub => self.sum(expr, left, right, -1., depth),
+                BinOp::Mul => self.product(expr, left, right, depth),
+                BinOp::Div => self.quotient(expr, left, right, depth),
+                BinOp::Pow => self.power(expr, left, right, depth),
+            },
+            AST::Function { name, args } if args.len() == 1 => {
+                self.function(expr, name, &args[0], depth)
+            }
+            _ => Err(self.indeterminate(expr)),
+        };
+    }
+    fn sum(
+        &self,
+        expr: &AST,
+        left: &AST,
+        right: &AST,
+        sign: f64,
+        depth: usize,
+    ) -> Result<Limit, Error> {
+        return match (self.limit(left, depth)?, self.limit(right, depth)?) {
+            (Limit::Finite(a), Limit::Finite(b)) => {
+                self.finite(expr, if sign > 0. { a + b } else { a - b })
+            }
+            (Limit::Infinite(a), Limit::Finite(_)) => Ok(Limit::Infinite(a)),
+            (Limit::Finite(_), Limit::Infinite(b)) => Ok(Limit::Infinite(sign * b)),
+            (Limit::Infinite(a), Limit::Infinite(b)) if a == sign * b => Ok(Limit::Infinite(a)),
+            // ∞ - ∞
+            _ => Err(self.indeterminate(expr)),
+        };
+    }
+    fn product(&self, expr: &AST, left: &AST, right: &AST, depth: usize) -> Result<Limit, Error> {
+        return match (self.limit(left, depth)?, self.limit(right, depth)?) {
+            (Limit::Finite(a), Limit::Finite(b)) => self.finite(expr, a * b),
+            (Limit::Infinite(a), Limit::Infinite(b)) => Ok(Limit::Infinite(a * b)),
+            (Limit::Infinite(sign), Limit::Finite(value)) => {
+                self.scaled_infinity(expr, sign, &value, right, left, depth)
+            }
+            (Limit::Finite(value), Limit::Infinite(sign)) => {
+                self.scaled_infinity(expr, sign, &value, left, right, depth)
+            }
+        };
+    }
+    /// The limit of `finite * infinite`, where `finite` tends to `value`.
+    fn scaled_infinity(
+        &self,
+        expr: &AST,
+        sign: f64,
+        value: &AST,
+        finite: &AST,
+        infinite: &AST,
+        depth: usize,
+    ) -> Result<Limit, Error> {
+        return match self.sign_of(value) {
+            // 0·∞ as g / (1 / f), or failing that f / (1 / g)
+            Some(0.) => {
+                let reciprocal = |ast: &AST| -> Result<AST, Error> {
+                    let mut reciprocal = AST::Literal { value: 1. } / ast.clone();
+                    reciprocal.simplify_with(self.registry)?;
+                    return Ok(reciprocal);
+                };
+                match self.quotient(expr, infinite, &reciprocal(finite)?, depth) {
+                    Ok(limit) => Ok(limit),
+                    Err(_) => self.quotient(expr, finite, &reciprocal(infinite)?, depth),
+                }
+            }
+            Some(value_sign) => Ok(Limit::Infinite(sign * value_sign)),
+            None => Err(self.indeterminate(expr)),
+        };
+    }
+    fn quotient(
+        &self,
+        expr: &AST,
+        numerator: &AST,
+        denominator: &AST,
+        depth: usize,
+    ) -> Result<Limit, Error> {
+        let numerator_limit = self.limit(numerator, depth)?;
+        let denominator_limit = self.limit(denominator, depth)?;
+        let numerator_zero = self.is_zero(&numerator_limit);
+        let denominator_zero = self.is_zero(&denominator_limit);
+        return match (numerator_limit, denominator_limit) {
+            (Limit::Finite(_), Limit::Finite(_)) if numerator_zero && denominator_zero => {
+                self.lhopital(expr, numerator, denominator, depth)
+            }
+            (Limit::Infinite(_), Limit::Infinite(_)) => {
+                self.lhopital(expr, numerator, denominator, depth)
+            }
+            (_, Limit::Finite(_)) if denominator_zero => self.divergence(expr),
+            (Limit::Finite(a), Limit::Finite(b)) => self.finite(expr, a / b),
+            (Limit::Finite(_), Limit::Infinite(_)) => Ok(Limit::Finite(AST::Literal { value: 0. })),
+            (Limit::Infinite(sign), Limit::Finite(value)) => match self.sign_of(&value) {
+                Some(value_sign) if value_sign != 0. => Ok(Limit::Infinite(sign * value_sign)),
+                _ => Err(self.indeterminate(expr)),
+            },
+        };
+    }
+    /// `lim f/g = lim f'/g'` when `f/g` is `0/0` or `∞/∞`.
+    fn lhopital(
+        &self,
+        expr: &AST,
+        numerator: &AST,
+        denominator: &AST,
+        depth: usize,
+    ) -> Result<Limit, Error> {
+        if depth >= MAX_LHOPITAL_STEPS {
+            return Err(self.indeterminate(expr));
+        }
+        if !self.is_differentiable(numerator) || !self.is_differentiable(denominator) {
+            return self.divergence(expr);
+        }
+        let numerator = numerator.differentiate_wrt_with(self.var, self.registry)?;
+        let denominator = denominator.differentiate_wrt_with(self.var, self.registry)?;
+        let mut quotient = numerator / denominator;
+        quotient.simplify_with(self.registry)?;
+        return self.limit(&quotient, depth + 1);
+    }
+    fn power(&self, expr: &AST, base: &AST, exponent: &AST, depth: usize) -> Result<Limit, Error> {
+        let base_limit = self.limit(base, depth)?;
+        let exponent_limit = self.limit(exponent, depth)?;
+        let base_value = self.value_of(&base_limit);
+        let exponent_value = self.value_of(&exponent_limit);
+        let indeterminate = match (base_value, exponent_value) {
+            (Some(base), Some(exponent)) => {
+                (base == 1. && exponent.is_infinite())
+                    || (base == 0. && exponent == 0.)
+                    || (base.is_infinite() && exponent == 0.)
+            }
+            _ => false,
+        };
+        if indeterminate {
+            // f^g = exp(g ln(f))
+            let mut logarithm = exponent.clone() * call("ln", base.clone());
+            logarithm.simplify_with(self.registry)?;
+            return match self.limit(&logarithm, depth)? {
+                Limit::Finite(value) => self.finite(expr, call("exp", value)),
+                Limit::Infinite(sign) if sign > 0. => Ok(Limit::Infinite(1.)),
+                Limit::Infinite(_) => Ok(Limit::Finite(AST::Literal { value: 0. })),
+            };
+        }
+        return match (base_limit, exponent_limit) {
+            (Limit::Finite(base), Limit::Finite(exponent)) => {
+                match self.defined(base.pow(exponent)) {
+                    Some(value) => Ok(Limit::Finite(value)),
+                    None => self.divergence(expr),
+                }
+            }
+            _ => self.numeric(expr, base_value, exponent_value, f64::powf),
+        };
+    }
+    fn function(&self, expr: &AST, name: &str, arg: &AST, depth: usize) -> Result<Limit, Error> {
+        return match self.limit(arg, depth)? {
+            Limit::Finite(value) => {
+                let call = call(name, value);
+                match self.defined(call) {
+                    Some(value) => Ok(Limit::Finite(value)),
+                    None => self.divergence(expr),
+                }
+            }
+            Limit::Infinite(sign) => {
+                let value = self
+                    .registry
+                    .get(name)
+                    .and_then(|definition| definition.evaluate(&[infinity_value(sign)]).ok());
+                self.numeric(expr, value, Some(0.), |value, _| value)
+            }
+        };
+    }
+    /// Whether no function in `expr` is defined but not differentiable at the value its
+    /// argument takes at a finite point, like `abs` or `sqrt` at `0`; L'Hôpital's rule is not
+    /// applied through such a function. Functions undefined there, like `ln` at `0`, and
+    /// arguments that cannot be evaluated are fine.
+    fn is_differentiable(&self, expr: &AST) -> bool {
+        let point = match self.numeric_point {
+            Some(point) if point.is_finite() => point,
+            _ => return true,
+        };
+        let env = HashMap::from([(self.var.to_string(), point)]);
+        return expr.fold(|node, children: Vec<bool>| {
+            if children.iter().any(|differentiable| !differentiable) {
+                return false;
+            }
+            let (name, arg) = match node {
+                AST::Function { name, args } if args.len() == 1 && name != "-" => (name, &args[0]),
+                _ => return true,
+            };
+            let value = match arg.evaluate_with(&env, self.registry) {
+                Ok(value) if value.is_finite() => value,
+                _ => return true,
+            };
+            if is_kinked(name) && value.abs() < ZERO_TOLERANCE {
+                return false;
+            }
+            let var = AST::Variable {
+                name: self.var.to_string(),
+            };
+            let at_value = HashMap::from([(self.var.to_string(), value)]);
+            let function = call(name, var);
+            match function.evaluate_with(&at_value, self.registry) {
+                Ok(value) if value.is_finite() => (),
+                // Undefined at the point, which is what L'Hôpital's rule is for.
+                _ => return true,
+            }
+            let slope = function
+                .differentiate_wrt_with(self.var, self.registry)
+                .map(|derivative| derivative.evaluate_with(&at_value, self.registry));
+            return matches!(slope, Ok(Ok(slope)) if slope.is_finite());
+        });
+    }
+    /// Combines numeric limits, where infinite ones are `±inf`, with `op`.
+    fn numeric(
+        &self,
+        expr: &AST,
+        a: Option<f64>,
+        b: Option<f64>,
+        op: fn(f64, f64) -> f64,
+    ) -> Result<Limit, Error> {
+        let value = match (a, b) {
+            (Some(a), Some(b)) => op(a, b),
+            _ => return Err(self.indeterminate(expr)),
+        };
+        if value.is_nan() {
+            return Err(self.indeterminate(expr));
+        }
+        if value.is_infinite() {
+            return Ok(Limit::Infinite(value));
+        }
+        return Ok(Limit::Finite(AST::Literal { value }));
+    }
+    /// A finite limit built from the limits of the operands, if it is defined.
+    fn finite(&self, expr: &AST, value: AST) -> Result<Limit, Error> {
+        return match self.defined(value) {
+            Some(value) => Ok(Limit::Finite(value)),
+            None => self.divergence(expr),
+        };
+    }
+    /// At `±inf`, the limit of a polynomial or a quotient of polynomials from the degrees and
+    /// leading coefficients.
+    fn rational_at_infinity(&self, expr: &AST, infinity: f64) -> Option<Limit> {
+        let (numerator, denominator) = match expr {
+            AST::BinOp {
+                op: BinOp::Div,
+                left,
+                right,
+            } => (
+                as_polynomial(left, self.var)?,
+                as_polynomial(right, self.var)?,
+            ),
+            _ => (as_polynomial(expr, self.var)?, vec![1.]),
+        };
+        let degree = |coefficients: &[f64]| coefficients.iter().rposition(|c| *c != 0.);
+        let (n, d) = (degree(&numerator), degree(&denominator)?);
+        let n = match n {
+            Some(n) => n,
+            None => return Some(Limit::Finite(AST::Literal { value: 0. })),
+        };
+        let ratio = numerator[n] / denominator[d];
+        return Some(if n < d {
+            Limit::Finite(AST::Literal { value: 0. })
+        } else if n == d {
+            Limit::Finite(AST::Literal { value: ratio })
+        } else {
+            Limit::Infinite(ratio.signum() * infinity.signum().powi((n - d) as i32))
+        });
+    }
+    /// Decides between `+inf` and `-inf` for an expression that is undefined at the point by
+    /// checking that it grows in magnitude, with a consistent sign, as the point is
+    /// approached from each allowed side.
+    fn divergence(&self, expr: &AST) -> Result<Limit, Error> {
+        let point = match self.numeric_point {
+            Some(point) => point,
+            None => return Err(self.indeterminate(expr)),
+        };
+        let sides: &[f64] = match (self.infinity, self.direction) {
+            (Some(infinity), _) => &[infinity.signum()],
+            (None, Direction::Both) => &[-1., 1.],
+            (None, Direction::Left) => &[-1.],
+            (None, Direction::Right) => &[1.],
+        };
+        let mut signs = Vec::new();
+        for side in sides {
+            let samples: Vec<f64> = match self.infinity {
+                Some(_) => FAR_POINTS.iter().map(|x| side * x).collect(),
+                None => NEAR_OFFSETS
+                    .iter()
+                    .map(|offset| point + side * offset * point.abs().max(1.))
+                    .collect(),
+            };
+            let mut env = HashMap::new();
+            let mut values = Vec::new();
+            for x in samples {
+                env.insert(self.var.to_string(), x);
+                match expr.evaluate_with(&env, self.registry) {
+                    Ok(value) if !value.is_nan() => values.push(value),
+                    _ => break,
+                }
+            }
+            if values.len() < 2 {
+                // Undefined on this side, so only the other side counts. Later samples may
+                // fail when the value overflows, which is fine as long as two are left.
+                continue;
+            }
+            let sign = values[0].signum();
+            let grows = values.windows(2).all(|pair| pair[1].abs() > pair[0].abs());
+            if !grows || values.iter().any(|value| value.signum() != sign) {
+                return Err(self.indeterminate(expr));
+            }
+            signs.push(sign);
+        }
+        return match signs.as_slice() {
+            [] => Err(self.indeterminate(expr)),
+            [sign] => Ok(Limit::Infinite(*sign)),
+            [left, right] if left == right => Ok(Limit::Infinite(*left)),
+            _ => Err(LimitError::DoesNotExist {
+                expr: expr.to_string(),
+                approach: self.approach(),
+            }
+            .into()),
+        };
+    }
+    /// `value` simplified, or `None` if it is undefined, i.e. contains `inf` or `NaN`, cannot
+    /// be evaluated or is too large to be anything but a pole.
+    fn defined(&self, mut value: AST) -> Option<AST> {
+        value.simplify_with(self.registry).ok()?;
+        let finite = value.fold(|node, children: Vec<bool>| match node {
+            AST::Literal { value } => value.is_finite(),
+            _ => children.into_iter().all(|finite| finite),
+        });
+        if !finite {
+            return None;
+        }
+        if value.variables().is_empty() {
+            let number = value.evaluate_with(&HashMap::new(), self.registry).ok()?;
+            if !number.is_finite() || number.abs() >= POLE_MAGNITUDE {
+                return None;
+            }
+            if number == 0. {
+                // The simplifier leaves `-(0)` alone.
+                return Some(AST::Literal { value: 0. });
+            }
+        }
+        return Some(value);
+    }
+    fn is_zero(&self, limit: &Limit) -> bool {
+        return matches!(limit, Limit::Finite(value) if self.sign_of(value) == Some(0.));
+    }
+    /// The sign of a constant limit as `-1`, `0` or `1`, or `None` if it is not a number.
+    fn sign_of(&self, value: &AST) -> Option<f64> {
+        let number = value.evaluate_with(&HashMap::new(), self.registry).ok()?;
+        return Some(if number.abs() < ZERO_TOLERANCE {
+            0.
+        } else {
+            number.signum()
+        });
+    }
+    /// A limit as a number, with infinite limits as `±inf`.
+    fn value_of(&self, limit: &Limit) -> Option<f64> {
+        return match limit {
+            Limit::Finite(value) => {
+                let number = value.evaluate_with(&HashMap::new(), self.registry).ok()?;
+                Some(if number.abs() < ZERO_TOLERANCE {
+                    0.
+                } else {
+                    number
+                })
+            }
+            Limit::Infinite(sign) => Some(infinity_value(*sign)),
+        };
+    }
+    fn approach(&self) -> String {
+        let arrow = match (self.infinity, self.direction) {
+            (None, Direction::Left) => "⁻",
+            (None, Direction::Right) => "⁺",
+            _ => "",
+        };
+        return format!("{} → {}{}", self.var, self.point, arrow);
+    }
+    fn indeterminate(&self, expr: &AST) -> Error {
+        return LimitError::Indeterminate {
+            expr: expr.to_string(),
+            approach: self.approach(),
+        }
+        .into();
+    }
+}
+
+/// Whether `name` is a function with a jump or kink at `0` that [`Solver::one_sided`] rewrites.
+fn is_kinked(name: &str) -> bool {
+    return matches!(name, "abs" | "sign" | "step");
+}
+
+fn infinity_value(sign: f64) -> f64 {
+    return if sign < 0. {
+        f64::NEG_INFINITY
+    } else {
+        f64::INFINITY
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{limit, Direction};
+    use crate::{parse_str, Error};
+
+    fn limit_of(source: &str, point: &str, direction: Direction) -> Result<String, Error> {
+        let expr = parse_str(source).unwrap();
+        let point = parse_str(point).unwrap();
+        return limit(&expr, "x", &point, direction).map(|limit| limit.to_string());
+    }
+
+    #[test]
+    fn one_sided_limits_at_jumps() {
+        assert_eq!(limit_of("abs(x) / x", "0", Direction::Right).unwrap(), "1");
+        assert_eq!(limit_of("abs(x) / x", "0", Direction::Left).unwrap(), "-1");
+        assert!(limit_of("abs(x) / x", "0", Direction::Both).is_err());
+        assert_eq!(limit_of("sign(x)", "0", Direction::Right).unwrap(), "1");
+        assert_eq!(limit_of("sign(x)", "0", Direction::Left).unwrap(), "-1");
+        assert!(limit_of("sign(x)", "0", Direction::Both).is_err());
+        assert_eq!(limit_of("x * sign(x)", "0", Direction::Both).unwrap(), "0");
+        assert_eq!(limit_of("abs(x - 1)", "1", Direction::Both).unwrap(), "0");
+    }
+
+    #[test]
+    fn lhopital_and_poles() {
+        assert_eq!(limit_of("sin(x) / x", "0", Direction::Both).unwrap(), "1");
+        assert_eq!(
+            limit_of("(1 - cos(x)) / x^2", "0", Direction::Both).unwrap(),
+            "0.5"
+        );
+        assert_eq!(limit_of("x * ln(x)", "0", Direction::Right).unwrap(), "0");
+        assert_eq!(limit_of("1 / x", "0", Direction::Left).unwrap(), "-inf");
+        assert!(limit_of("1 / x", "0", Direction::Both).is_err());
+        assert_eq!(limit_of("-1 / x^2", "0", Direction::Both).unwrap(), "-inf");
+        assert_eq!(
+            limit_of("sin(1 / x)", "0", Direction::Both)
+                .unwrap_err()
+                .to_string(),
+            "limit error: the limit of `sin(1 / x)` as x → 0 does not exist"
+        );
+        assert_eq!(limit_of("1 / x^2", "0", Direction::Both).unwrap(), "inf");
+        // sqrt is not differentiable at 0, so this is settled by sampling instead.
+        assert_eq!(
+            limit_of("sqrt(x) / x", "0", Direction::Right).unwrap(),
+            "inf"
+        );
+    }
+
+    #[test]
+    fn limits_at_infinity() {
+        let rational = limit_of("(2*x^2 + 1) / (x^2 - 3)", "inf", Direction::Both);
+        assert_eq!(rational.unwrap(), "2");
+        assert_eq!(
+            limit_of("(1 + 1/x)^x", "inf", Direction::Both).unwrap(),
+            "exp(1)"
+        );
+        assert_eq!(limit_of("exp(-x)", "inf", Direction::Both).unwrap(), "0");
+    }
+}
@@ -1,16 +1,15 @@
-mod ast;
-mod lexer;
-mod parser;
-mod tokens;
-mod simplifier;
-mod differentiator;
+#![allow(clippy::needless_return)]
 
 use std::{
+    collections::{BTreeSet, HashMap},
     io::{self, Write},
 };
 
-use lexer::lex;
-use parser::parse;
+use differentiator::{
+    analyze, find_root, find_root_system, gradient, hessian, integrate_definite, jacobian, lex,
+    limit, parse_equation_with, parse_with, render_analysis, render_error, render_matrix, solve,
+    taylor, taylor_remainder_bound, Direction, Error, ParseOptions, SpannedToken, Token, AST,
+};
 
 fn main() {
     let stdin = std::io::stdin();
@@ -27,43 +26,478 @@ fn main() {
             break;
         }
 
-        let tokens = match lex(&input) {
+        let (orders, expression) = match split_derivative_prefix(&input) {
+            Ok(split) => split,
+            Err(message) => {
+                eprintln!("{}", message);
+                continue;
+            }
+        };
+        let tokens = match lex(expression) {
             Ok(tokens) => tokens,
             Err(err) => {
-                eprintln!("lex error {}", err);
+                eprintln!(
+                    "{}",
+                    render_error(expression, err.span(), &format!("lex error: {}", err))
+                );
                 continue;
             }
         };
-        println!("tokens read: {:?}", tokens);
-        let mut ast = match parse(&tokens) {
+        println!(
+            "tokens read: {:?}",
+            tokens.iter().map(|token| &token.token).collect::<Vec<_>>()
+        );
+        let options = ParseOptions {
+            implicit_multiplication: true,
+            extra_functions: COMMANDS.iter().map(|(name, _)| name.to_string()).collect(),
+            ..Default::default()
+        };
+        if tokens
+            .iter()
+            .any(|token| matches!(token.token, Token::Equals))
+        {
+            match solve_equation(expression, &tokens, &options) {
+                Ok(output) => println!("{}", output),
+                Err(message) => eprintln!("{}", message),
+            }
+            continue;
+        }
+        let mut ast = match parse_with(&tokens, &options) {
             Ok(ast) => ast,
             Err(err) => {
-                eprintln!("parse error {}", err);
+                eprintln!(
+                    "{}",
+                    render_error(expression, err.span(), &format!("parse error: {}", err))
+                );
                 continue;
             }
         };
+        if let Some(output) = run_command(&ast) {
+            match output {
+                Ok(output) => println!("{}", output),
+                Err(err) => eprintln!("{}", err),
+            }
+            continue;
+        }
 
-        println!("input read as: {ast}\n{ast:?}", ast=ast);
-        match ast.simplify() {
+        println!("input read as: {ast}\n{ast:?}", ast = ast);
+        // Chosen before simplifying, which may cancel the variable, as in `x - x`.
+        let only_variable = ast.only_variable();
+        if let Err(err) = ast.simplify() {
+            eprintln!("evaluation error: {}", err);
+            continue;
+        }
+        println!("input simplified to: {ast}\n{ast:?}", ast = ast);
+
+        let derivative = match orders.as_deref() {
+            Some([(var, 1)]) => ast.differentiate_wrt(var),
+            Some(orders) => {
+                let orders: Vec<(&str, usize)> =
+                    orders.iter().map(|(var, n)| (var.as_str(), *n)).collect();
+                match ast.mixed_derivative(&orders) {
+                    Ok(derivative) => println!(
+                        "derivative simplified to: {derivative}\n{derivative:?}",
+                        derivative = derivative
+                    ),
+                    Err(err) => eprintln!("{}", err),
+                }
+                continue;
+            }
+            None => only_variable.and_then(|var| match var {
+                Some(var) => ast.differentiate_wrt(&var),
+                None => Ok(AST::Literal { value: 0. }),
+            }),
+        };
+        let mut derivative = match derivative {
+            Ok(derivative) => derivative,
             Err(err) => {
-                eprintln!("evaluation error: {}", err);
+                eprintln!("differentiation error: {}", err);
                 continue;
             }
-            _ => (),
+        };
+
+        println!(
+            "derivative calculated: {derivative}\n{derivative:?}",
+            derivative = derivative
+        );
+        if let Err(err) = derivative.simplify() {
+            eprintln!("evaluation error: {}", err);
+            continue;
+        }
+        println!(
+            "derivative simplified to: {derivative}\n{derivative:?}",
+            derivative = derivative
+        );
+    }
+}
+
+/// The names [`run_command`] and `solve_equation` handle, which are not functions, with how
+/// to call them.
+const COMMANDS: [(&str, &str); 8] = [
+    ("int", "int(expr, var) or int(expr, var, a, b)"),
+    (
+        "limit",
+        "limit(expr, var, point) or limit(expr, var, point, left|right)",
+    ),
+    (
+        "series",
+        "series(expr, var, point, order) or series(expr, var, point, order, at)",
+    ),
+    ("analyze", "analyze(expr, var, a, b)"),
+    (
+        "solve",
+        "solve(lhs = rhs, var), or solve(exprs, vars, guesses) with as many of each",
+    ),
+    ("gradient", "gradient(expr)"),
+    ("hessian", "hessian(expr)"),
+    ("jacobian", "jacobian(expr, ...)"),
+];
+
+/// Runs the input as a command if it is a call to one, e.g. `hessian(x^2*y)`, `int(x*exp(x), x)`,
+/// `int(x^2*sin(x), x, 0, pi)`, `series(sin(x)/x, x, 0, 6)`, `limit(1/x, x, 0, right)`,
+/// `solve(x^3 - 2x - 5, x, 2)` or `analyze(x^3 - 3x, x, -3, 3)`. Returns `None` for ordinary
+/// expressions, and a usage message for a command whose arguments do not fit it.
+fn run_command(ast: &AST) -> Option<Result<String, String>> {
+    let (name, args) = match ast {
+        AST::Function { name, args } => (name.as_str(), args),
+        _ => return None,
+    };
+    let usage = COMMANDS
+        .iter()
+        .find(|(command, _)| *command == name)
+        .map(|(_, usage)| *usage)?;
+    let args: Vec<AST> = args.iter().map(|arg| (**arg).clone()).collect();
+    return Some(command(name, &args).unwrap_or_else(|| Err(format!("usage: {}", usage))));
+}
+
+/// Runs the command `name` on `args`, or returns `None` if they do not fit it.
+fn command(name: &str, args: &[AST]) -> Option<Result<String, String>> {
+    let output = match (name, args) {
+        ("int", [expr, AST::Variable { name: var }]) => expr
+            .integrate(var)
+            .map(|integral| format!("integral simplified to: {} + C", integral)),
+        ("int", [expr, AST::Variable { name: var }, a, b]) => definite_integral(expr, var, a, b),
+        ("limit", [expr, AST::Variable { name: var }, point, rest @ ..]) => {
+            let direction = match rest {
+                [] => Direction::Both,
+                [AST::Variable { name }] if name == "left" => Direction::Left,
+                [AST::Variable { name }] if name == "right" => Direction::Right,
+                _ => return None,
+            };
+            limit(expr, var, point, direction).map(|limit| format!("limit = {}", limit))
+        }
+        (
+            "series",
+            [expr, AST::Variable { name: var }, point, AST::Literal { value: order }, rest @ ..],
+        ) if order.fract() == 0. && *order >= 0. && rest.len() <= 1 => {
+            series(expr, var, point, *order as usize, rest.first())
         }
-        println!("input simplified to: {ast}\n{ast:?}", ast=ast);
+        ("analyze", [expr, AST::Variable { name: var }, a, b]) => analysis(expr, var, a, b),
+        ("solve", _) if !args.is_empty() && args.len().is_multiple_of(3) => {
+            let n = args.len() / 3;
+            let vars: Option<Vec<&str>> = args[n..2 * n]
+                .iter()
+                .map(|var| match var {
+                    AST::Variable { name } => Some(name.as_str()),
+                    _ => None,
+                })
+                .collect();
+            find_roots(&args[..n], &vars?, &args[2 * n..])
+        }
+        ("gradient" | "hessian", [_]) | ("jacobian", [_, ..]) => matrix(name, args),
+        _ => return None,
+    };
+    return Some(output.map_err(|err| err.to_string()));
+}
 
-        let mut derivative = ast.clone().differentiate();
-        
-        println!("derivative calculated: {derivative}\n{derivative:?}", derivative=derivative);
-        match derivative.simplify() {
-            Err(err) => {
-                eprintln!("evaluation error: {}", err);
-                continue;
+/// The gradient, Hessian or Jacobian of `exprs` with respect to all of their variables.
+fn matrix(name: &str, exprs: &[AST]) -> Result<String, Error> {
+    let variables: BTreeSet<String> = exprs.iter().flat_map(AST::variables).collect();
+    let vars: Vec<&str> = variables.iter().map(String::as_str).collect();
+    let matrix = match name {
+        "gradient" => gradient(&exprs[0], &vars)
+            .map(|gradient| gradient.into_iter().map(|partial| vec![partial]).collect()),
+        "hessian" => hessian(&exprs[0], &vars),
+        _ => jacobian(exprs, &vars),
+    }?;
+    return Ok(format!(
+        "with respect to ({}):\n{}",
+        vars.join(", "),
+        render_matrix(&matrix)
+    ));
+}
+
+fn definite_integral(expr: &AST, var: &str, a: &AST, b: &AST) -> Result<String, Error> {
+    let env = HashMap::new();
+    let integral = integrate_definite(expr, var, a.evaluate(&env)?, b.evaluate(&env)?)?;
+    return Ok(match integral.antiderivative {
+        Some(antiderivative) => format!(
+            "integral = {}\nfrom antiderivative: {}",
+            integral.value, antiderivative
+        ),
+        None => format!(
+            "integral ≈ {} ± {:.1e} by quadrature",
+            integral.value, integral.error
+        ),
+    });
+}
+
+/// The stationary points of `expr` between `a` and `b` and where it rises and falls.
+fn analysis(expr: &AST, var: &str, a: &AST, b: &AST) -> Result<String, Error> {
+    let env = HashMap::new();
+    let analysis = analyze(expr, var, (a.evaluate(&env)?, b.evaluate(&env)?))?;
+    return Ok(render_analysis(&analysis, var));
+}
+
+/// Solves `solve(2x + 3 = 7, x)`, or an equation in a single variable such as `x^2 = 4`.
+fn solve_equation(
+    source: &str,
+    tokens: &[SpannedToken],
+    options: &ParseOptions,
+) -> Result<String, String> {
+    let kinds: Vec<&Token> = tokens.iter().map(|token| &token.token).collect();
+    let (tokens, var) = match kinds.as_slice() {
+        [Token::Function(name), Token::OpenParen, .., Token::Comma, Token::Variable(var), Token::CloseParen]
+            if name == "solve" =>
+        {
+            (&tokens[2..tokens.len() - 3], Some(var.clone()))
+        }
+        _ => (tokens, None),
+    };
+    let equation = parse_equation_with(tokens, options)
+        .map_err(|err| render_error(source, err.span(), &format!("parse error: {}", err)))?;
+    let var = match var {
+        Some(var) => var,
+        None => {
+            let mut variables = equation.left.variables();
+            variables.append(&mut equation.right.variables());
+            if variables.len() != 1 {
+                return Err(format!(
+                    "name the variable to solve for, e.g. `solve({}, x)`",
+                    equation
+                ));
             }
-            _ => (),
+            variables.pop_first().unwrap()
         }
-        println!("derivative simplified to: {derivative}\n{derivative:?}", derivative=derivative);
+    };
+    let solutions = solve(&equation, &var).map_err(|err| err.to_string())?;
+    if solutions.is_empty() {
+        return Ok(format!("no real solutions for {}", var));
     }
+    let lines: Vec<String> = solutions
+        .iter()
+        .map(|solution| format!("{} = {}", var, solution))
+        .collect();
+    return Ok(lines.join("\n"));
+}
+
+/// Finds a root of one expression, or a common root of several, from `solve(f, x, x0)` or
+/// `solve(f, g, x, y, x0, y0)`.
+fn find_roots(exprs: &[AST], vars: &[&str], starts: &[AST]) -> Result<String, Error> {
+    let env = HashMap::new();
+    let starts = starts
+        .iter()
+        .map(|start| start.evaluate(&env))
+        .collect::<Result<Vec<f64>, _>>()?;
+    let root = match exprs {
+        [expr] => vec![find_root(expr, vars[0], starts[0])?],
+        _ => find_root_system(exprs, vars, &starts)?,
+    };
+    let assignments: Vec<String> = vars
+        .iter()
+        .zip(&root)
+        .map(|(name, value)| format!("{} = {}", name, value))
+        .collect();
+    return Ok(format!("root: {}", assignments.join(", ")));
+}
+
+/// The Taylor polynomial, and if `at` is given, a bound on its error there.
+fn series(
+    expr: &AST,
+    var: &str,
+    point: &AST,
+    order: usize,
+    at: Option<&AST>,
+) -> Result<String, Error> {
+    let polynomial = taylor(expr, var, point, order)?;
+    let at = match at {
+        Some(at) => at.evaluate(&HashMap::new())?,
+        None => return Ok(format!("series: {}", polynomial)),
+    };
+    let bound = taylor_remainder_bound(expr, var, point, order, at)?;
+    return Ok(format!(
+        "series: {}\nremainder at {} = {} is at most about {:.3e}",
+        polynomial, var, at, bound
+    ));
 }
 
+/// How many times to differentiate with respect to each variable, in order.
+type Orders = Vec<(String, usize)>;
+
+/// Splits a leading derivative operator off the input, returning how often to differentiate
+/// with respect to each variable. `d/dy x*y^2` gives `[("y", 1)]`, `d3/dx3 f` gives
+/// `[("x", 3)]`, and `d2/dxdy f` and `d2/dx dy f` both give `[("x", 1), ("y", 1)]`.
+///
+/// Without an order, the variable is the longest run of letters after the `d`, so `d/dydx f`
+/// differentiates once by `ydx`. With one, the denominator is read as `d` parts whose powers
+/// add up to the order, preferring the longest variables: `d2/ddelta2 f` differentiates twice
+/// by `delta`, while `d2/dydx f` falls back to splitting `ydx` at its `d`.
+fn split_derivative_prefix(input: &str) -> Result<(Option<Orders>, &str), String> {
+    let not_a_prefix = Ok((None, input));
+    let (order, denominator) = match input
+        .strip_prefix('d')
+        .and_then(|rest| rest.split_once("/d"))
+    {
+        Some(split) => split,
+        None => return not_a_prefix,
+    };
+    if !order.chars().all(|character| character.is_ascii_digit()) {
+        return not_a_prefix;
+    }
+    if order.is_empty() {
+        let length = variable_length(denominator);
+        if length == 0 {
+            return Err("expected a variable after `d/d`".to_string());
+        }
+        let (var, rest) = denominator.split_at(length);
+        return Ok((Some(vec![(var.to_string(), 1)]), rest.trim_start()));
+    }
+    let expected = order
+        .parse()
+        .map_err(|_| format!("`d{}` is too many differentiations", order))?;
+    return match derivative_parts(denominator, expected)? {
+        Some((orders, rest)) => Ok((Some(orders), rest.trim_start())),
+        None => Err(format!(
+            "`d{}` does not match the differentiations in `d{}`",
+            order,
+            denominator.split_whitespace().next().unwrap_or("")
+        )),
+    };
+}
+
+/// Reads `rest`, the part of a denominator after a `d`, as variables with optional powers
+/// that add up to `remaining`, together with the input after them. A variable ends at the end
+/// of its run of letters or, failing that, before any `d` inside the run, longest first.
+fn derivative_parts(rest: &str, remaining: usize) -> Result<Option<(Orders, &str)>, String> {
+    let run = variable_length(rest);
+    let mut ends = vec![run];
+    ends.extend(
+        (1..run)
+            .rev()
+            .filter(|end| rest.is_char_boundary(*end) && rest[*end..].starts_with('d')),
+    );
+    for end in ends {
+        let (var, mut after) = rest.split_at(end);
+        let mut power = 1;
+        if end == run {
+            after = after.strip_prefix('^').unwrap_or(after);
+            let digits = after
+                .find(|character: char| !character.is_ascii_digit())
+                .unwrap_or(after.len());
+            if digits > 0 {
+                power = after[..digits]
+                    .parse()
+                    .map_err(|_| format!("`{}` is too many differentiations", &after[..digits]))?;
+            }
+            after = &after[digits..];
+        }
+        if power == 0 || power > remaining {
+            continue;
+        }
+        if power == remaining {
+            return Ok(Some((vec![(var.to_string(), power)], after)));
+        }
+        if let Some(next) = after.trim_start().strip_prefix('d') {
+            if let Some((mut orders, after)) = derivative_parts(next, remaining - power)? {
+                orders.insert(0, (var.to_string(), power));
+                return Ok(Some((orders, after)));
+            }
+        }
+    }
+    return Ok(None);
+}
+
+/// The length of the run of variable characters at the start of `text`.
+fn variable_length(text: &str) -> usize {
+    return text
+        .find(|character: char| !is_variable_character(character))
+        .unwrap_or(text.len());
+}
+
+/// Whether `character` may appear in a variable of a derivative operator; digits are left
+/// for the power.
+fn is_variable_character(character: char) -> bool {
+    return character.is_alphabetic() || character == '_' || character == '\'';
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{run_command, split_derivative_prefix};
+    use differentiator::parse_str;
+
+    fn orders(pairs: &[(&str, usize)]) -> Option<Vec<(String, usize)>> {
+        return Some(
+            pairs
+                .iter()
+                .map(|(var, power)| (var.to_string(), *power))
+                .collect(),
+        );
+    }
+
+    #[test]
+    fn variables_are_read_whole() {
+        assert_eq!(
+            split_derivative_prefix("d2/ddelta2 delta^3"),
+            Ok((orders(&[("delta", 2)]), "delta^3"))
+        );
+        assert_eq!(
+            split_derivative_prefix("d/dydx ydx^2"),
+            Ok((orders(&[("ydx", 1)]), "ydx^2"))
+        );
+        assert_eq!(
+            split_derivative_prefix("d2/dydx y*x"),
+            Ok((orders(&[("y", 1), ("x", 1)]), "y*x"))
+        );
+    }
+
+    #[test]
+    fn mixed_and_higher_orders() {
+        assert_eq!(
+            split_derivative_prefix("d3/dx3 x^4"),
+            Ok((orders(&[("x", 3)]), "x^4"))
+        );
+        assert_eq!(
+            split_derivative_prefix("d2/dx dy x^2*y^3"),
+            Ok((orders(&[("x", 1), ("y", 1)]), "x^2*y^3"))
+        );
+        assert_eq!(
+            split_derivative_prefix("d2/dxdy x^2*y^3"),
+            Ok((orders(&[("x", 1), ("y", 1)]), "x^2*y^3"))
+        );
+        assert_eq!(
+            split_derivative_prefix("d3/dx2dy x^2*y^3"),
+            Ok((orders(&[("x", 2), ("y", 1)]), "x^2*y^3"))
+        );
+        assert!(split_derivative_prefix("d3/dxdy x*y").is_err());
+        assert_eq!(
+            split_derivative_prefix("d3/dx^2 dy x^2*y^3"),
+            Ok((orders(&[("x", 2), ("y", 1)]), "x^2*y^3"))
+        );
+        assert_eq!(split_derivative_prefix("x^2"), Ok((None, "x^2")));
+    }
+
+    #[test]
+    fn commands_with_the_wrong_arguments_print_their_usage() {
+        let run = |source: &str| run_command(&parse_str(source).unwrap());
+        assert_eq!(
+            run("hessian(x, y)"),
+            Some(Err("usage: hessian(expr)".to_string()))
+        );
+        assert!(run("series(x, x, 0, 2, 1, 2)")
+            .unwrap()
+            .unwrap_err()
+            .starts_with("usage: series("));
+        assert!(run("hessian(x^2 * y)").unwrap().is_ok());
+        assert_eq!(run("sin(x)"), None);
+    }
+}
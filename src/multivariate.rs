@@ -0,0 +1,131 @@
+use crate::{ast::AST, functions::FunctionRegistry, table::align_columns, Error};
+
+/// The partial derivatives of `expr` with respect to each of `vars`, simplified.
+pub fn gradient(expr: &AST, vars: &[&str]) -> Result<Vec<AST>, Error> {
+    return gradient_with(expr, vars, FunctionRegistry::builtin());
+}
+
+/// [`gradient`] using the functions in `registry`.
+pub fn gradient_with(
+    expr: &AST,
+    vars: &[&str],
+    registry: &FunctionRegistry,
+) -> Result<Vec<AST>, Error> {
+    let mut gradient = Vec::with_capacity(vars.len());
+    for var in vars {
+        gradient.push(expr.nth_derivative_with(var, 1, registry)?);
+    }
+    return Ok(gradient);
+}
+
+/// The matrix of partial derivatives of `exprs`: row `i` is the gradient of `exprs[i]`, and
+/// column `j` holds the derivatives with respect to `vars[j]`.
+pub fn jacobian(exprs: &[AST], vars: &[&str]) -> Result<Vec<Vec<AST>>, Error> {
+    return jacobian_with(exprs, vars, FunctionRegistry::builtin());
+}
+
+/// [`jacobian`] using the functions in `registry`.
+pub fn jacobian_with(
+    exprs: &[AST],
+    vars: &[&str],
+    registry: &FunctionRegistry,
+) -> Result<Vec<Vec<AST>>, Error> {
+    return exprs
+        .iter()
+        .map(|expr| gradient_with(expr, vars, registry))
+        .collect();
+}
+
+/// The matrix of second partial derivatives of `expr`, where entry `(i, j)` is
+/// `∂²expr/∂vars[i]∂vars[j]`. Mixed partials are computed once and mirrored, since the
+/// matrix is symmetric.
+pub fn hessian(expr: &AST, vars: &[&str]) -> Result<Vec<Vec<AST>>, Error> {
+    return hessian_with(expr, vars, FunctionRegistry::builtin());
+}
+
+/// [`hessian`] using the functions in `registry`.
+pub fn hessian_with(
+    expr: &AST,
+    vars: &[&str],
+    registry: &FunctionRegistry,
+) -> Result<Vec<Vec<AST>>, Error> {
+    let gradient = gradient_with(expr, vars, registry)?;
+    let mut hessian: Vec<Vec<AST>> = Vec::with_capacity(vars.len());
+    for (i, partial) in gradient.iter().enumerate() {
+        let mut row = Vec::with_capacity(vars.len());
+        for (j, var) in vars.iter().enumerate() {
+            if j < i {
+                row.push(hessian[j][i].clone());
+            } else {
+                row.push(partial.nth_derivative_with(var, 1, registry)?);
+            }
+        }
+        hessian.push(row);
+    }
+    return Ok(hessian);
+}
+
+/// Lays out a matrix with aligned columns between tall brackets:
+///
+/// ```text
+/// ⎡ 2 * y  2 * x ⎤
+/// ⎣ 2 * x  0     ⎦
+/// ```
+pub fn render_matrix(rows: &[Vec<AST>]) -> String {
+    let cells: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| row.iter().map(AST::to_string).collect())
+        .collect();
+    if cells.is_empty() {
+        return "[ ]".to_string();
+    }
+    let aligned = align_columns(&cells);
+    let mut lines = Vec::with_capacity(cells.len());
+    for (index, row) in aligned.iter().enumerate() {
+        let (open, close) = match (index, cells.len()) {
+            (_, 1) => ("[", "]"),
+            (0, _) => ("⎡", "⎤"),
+            (index, rows) if index == rows - 1 => ("⎣", "⎦"),
+            _ => ("⎢", "⎥"),
+        };
+        lines.push(format!("{} {} {}", open, row, close));
+    }
+    return lines.join("\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{gradient, hessian, jacobian, render_matrix};
+    use crate::{parse_str, AST};
+
+    fn strings(row: &[AST]) -> Vec<String> {
+        return row.iter().map(AST::to_string).collect();
+    }
+
+    #[test]
+    fn partial_derivatives() {
+        let f = parse_str("x^2*y + sin(y)").unwrap();
+        assert_eq!(
+            strings(&gradient(&f, &["x", "y"]).unwrap()),
+            ["2 * y * x", "x ^ 2 + cos(y)"]
+        );
+        let hessian = hessian(&f, &["x", "y"]).unwrap();
+        assert_eq!(strings(&hessian[0]), ["2 * y", "2 * x"]);
+        assert_eq!(strings(&hessian[1]), ["2 * x", "-(sin(y))"]);
+        let functions = [parse_str("x*y").unwrap(), parse_str("x + y").unwrap()];
+        let jacobian = jacobian(&functions, &["x", "y"]).unwrap();
+        assert_eq!(strings(&jacobian[0]), ["y", "x"]);
+        assert_eq!(strings(&jacobian[1]), ["1", "1"]);
+    }
+
+    #[test]
+    fn matrices_are_aligned() {
+        let f = parse_str("x^2*y + sin(y)").unwrap();
+        assert_eq!(
+            render_matrix(&hessian(&f, &["x", "y"]).unwrap()),
+            "⎡ 2 * y  2 * x     ⎤\n⎣ 2 * x  -(sin(y)) ⎦"
+        );
+        assert_eq!(render_matrix(&[vec![parse_str("x").unwrap()]]), "[ x ]");
+        assert_eq!(render_matrix(&[]), "[ ]");
+    }
+}
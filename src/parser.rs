@@ -1,173 +1,405 @@
 use crate::ast::AST;
-use crate::tokens::{BinOp, Token};
-use std::{error::Error, fmt};
+use crate::equation::Equation;
+use crate::functions::FunctionRegistry;
+use crate::tokens::{BinOp, Span, SpannedToken, Token};
+use std::{error::Error, fmt, rc::Rc};
 
 #[derive(Debug)]
 pub enum ParseError {
-    EndOfStream,
-    UnclosedParen,
-    MissingParen,
-    UnexpectedOperator,
-    UnexpectedTokens,
+    /// The input ended where more was expected.
+    EndOfStream { span: Span },
+    /// The `(` at `span` was never closed.
+    UnclosedParen { span: Span },
+    /// A `)` was expected at `span` but `found` was there instead.
+    MissingParen { span: Span, found: String },
+    /// An operator appeared where an operand was expected.
+    UnexpectedOperator { span: Span, operator: BinOp },
+    /// A token that cannot appear at this position.
+    UnexpectedTokens { span: Span, found: String },
+    /// Parentheses, function calls and prefix operators are nested more than `limit` deep.
+    NestingTooDeep { span: Span, limit: usize },
+    /// Under implicit multiplication, `name` is followed by `(` but is not a known function.
+    UnknownFunction { span: Span, name: String },
+}
+
+impl ParseError {
+    /// Where in the source the error occurred.
+    pub fn span(&self) -> Span {
+        return match self {
+            ParseError::EndOfStream { span }
+            | ParseError::UnclosedParen { span }
+            | ParseError::MissingParen { span, .. }
+            | ParseError::UnexpectedOperator { span, .. }
+            | ParseError::UnexpectedTokens { span, .. }
+            | ParseError::NestingTooDeep { span, .. }
+            | ParseError::UnknownFunction { span, .. } => *span,
+        };
+    }
 }
 
 impl Error for ParseError {}
 
 impl fmt::Display for ParseError {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        return write!(formatter, "died parsing lol");
+        return match self {
+            ParseError::EndOfStream { .. } => write!(formatter, "unexpected end of input"),
+            ParseError::UnclosedParen { .. } => write!(formatter, "this `(` is never closed"),
+            ParseError::MissingParen { found, .. } => {
+                write!(formatter, "expected `)` but found `{}`", found)
+            }
+            ParseError::UnexpectedOperator { operator, .. } => {
+                write!(
+                    formatter,
+                    "expected an operand but found operator `{}`",
+                    operator
+                )
+            }
+            ParseError::UnexpectedTokens { found, .. } => {
+                write!(formatter, "unexpected `{}`", found)
+            }
+            ParseError::NestingTooDeep { limit, .. } => {
+                write!(
+                    formatter,
+                    "expression is nested more than {} levels deep",
+                    limit
+                )
+            }
+            ParseError::UnknownFunction { name, .. } => {
+                write!(
+                    formatter,
+                    "unknown function `{}`; write `{} * (...)` for a product",
+                    name, name
+                )
+            }
+        };
     }
 }
 
-pub fn parse(tokens: &Vec<Token>) -> Result<AST, ParseError> {
+/// Settings that change what the parser accepts.
+#[derive(Debug, Clone)]
+pub struct ParseOptions<'a> {
+    /// Read adjacent operands as a product, e.g. `2x`, `3 sin(x)` or `(x+1)(x-1)`. The implied
+    /// `*` binds exactly like a written one, so `2x^2` is `2*(x^2)` and `1/2x` is `(1/2)*x`.
+    /// A name before `(` must be a function in `registry` or one of `extra_functions`;
+    /// anything else fails with [`ParseError::UnknownFunction`], so `x(x+1)` has to be
+    /// written `x*(x+1)` or `x (x+1)`.
+    pub implicit_multiplication: bool,
+    /// The functions a name before `(` is looked up in under implicit multiplication.
+    pub registry: &'a FunctionRegistry,
+    /// Names besides the functions in `registry` that are still read as calls under implicit
+    /// multiplication, such as the commands of a REPL.
+    pub extra_functions: Vec<String>,
+    /// How deeply parentheses, function calls and prefix `-` or `√` may nest before parsing
+    /// fails with [`ParseError::NestingTooDeep`] instead of exhausting the stack. Operator
+    /// chains such as `x + x + ...` do not count towards it.
+    pub max_depth: usize,
+}
+
+impl Default for ParseOptions<'_> {
+    fn default() -> Self {
+        return ParseOptions {
+            implicit_multiplication: false,
+            registry: FunctionRegistry::builtin(),
+            extra_functions: Vec::new(),
+            max_depth: 128,
+        };
+    }
+}
+
+/// Parses with the default [`ParseOptions`].
+pub fn parse(tokens: &[SpannedToken]) -> Result<AST, ParseError> {
+    return parse_with(tokens, &ParseOptions::default());
+}
+
+pub fn parse_with(tokens: &[SpannedToken], options: &ParseOptions) -> Result<AST, ParseError> {
     let index = &mut 0;
-    let res = parse_term(tokens, index);
-    if *index != tokens.len() {
-        return Err(ParseError::UnexpectedTokens);
+    let res = parse_term(tokens, index, options, 0)?;
+    if let Some(token) = tokens.get(*index) {
+        return Err(unexpected(token));
     }
-    return res;
+    return Ok(res);
 }
 
-fn parse_term(tokens: &Vec<Token>, index: &mut usize) -> Result<AST, ParseError> {
-    let mut left = parse_factor(tokens, index)?;
-    loop {
-        let op_token = match consume_token(tokens, index) {
-            Ok(&Token::BinOp(token)) => match token {
-                BinOp::Add | BinOp::Sub => token,
-                _ => {
-                    *index -= 1;
-                    break;
-                }
-            },
-            _ => {
-                *index -= 1;
-                break;
-            }
-        };
-        let right = parse_factor(tokens, index)?;
-        left = AST::BinOp {
-            op: op_token,
-            left: Box::new(left),
-            right: Box::new(right),
-        };
+/// Parses an [`Equation`] with the default [`ParseOptions`].
+pub fn parse_equation(tokens: &[SpannedToken]) -> Result<Equation, ParseError> {
+    return parse_equation_with(tokens, &ParseOptions::default());
+}
+
+/// Parses two expressions separated by `=`.
+pub fn parse_equation_with(
+    tokens: &[SpannedToken],
+    options: &ParseOptions,
+) -> Result<Equation, ParseError> {
+    let index = &mut 0;
+    let left = parse_term(tokens, index, options, 0)?;
+    let equals = consume_token(tokens, index)?;
+    match equals.token {
+        Token::Equals => (),
+        _ => return Err(unexpected(equals)),
+    };
+    let right = parse_term(tokens, index, options, 0)?;
+    if let Some(token) = tokens.get(*index) {
+        return Err(unexpected(token));
+    }
+    return Ok(Equation { left, right });
+}
+
+fn parse_term(
+    tokens: &[SpannedToken],
+    index: &mut usize,
+    options: &ParseOptions,
+    depth: usize,
+) -> Result<AST, ParseError> {
+    let mut left = parse_factor(tokens, index, options, depth)?;
+    while let Some(&Token::BinOp(op_token @ (BinOp::Add | BinOp::Sub))) = peek_token(tokens, *index)
+    {
+        *index += 1;
+        let right = parse_factor(tokens, index, options, depth)?;
+        left = AST::binary(op_token, left, right);
     }
     return Ok(left);
 }
 
-fn parse_factor(tokens: &Vec<Token>, index: &mut usize) -> Result<AST, ParseError> {
-    let mut left = parse_pow(tokens, index)?;
+fn parse_factor(
+    tokens: &[SpannedToken],
+    index: &mut usize,
+    options: &ParseOptions,
+    depth: usize,
+) -> Result<AST, ParseError> {
+    let mut left = parse_pow(tokens, index, options, depth)?;
     loop {
-        let op_token = match consume_token(tokens, index) {
-            Ok(&Token::BinOp(token)) => match token {
-                BinOp::Mul | BinOp::Div => token,
-                _ => {
-                    *index -= 1;
-                    break;
-                }
-            },
-            _ => {
-                *index -= 1;
-                break;
+        let op_token = match peek_token(tokens, *index) {
+            Some(&Token::BinOp(op_token @ (BinOp::Mul | BinOp::Div))) => {
+                *index += 1;
+                op_token
             }
+            Some(
+                Token::Literal(_)
+                | Token::SymbolicConstant(_)
+                | Token::Variable(_)
+                | Token::Function(_)
+                | Token::OpenParen
+                | Token::Radical,
+            ) if options.implicit_multiplication => BinOp::Mul,
+            _ => break,
         };
-        let right = parse_pow(tokens, index)?;
-        left = AST::BinOp {
-            op: op_token,
-            left: Box::new(left),
-            right: Box::new(right),
-        };
+        let right = parse_pow(tokens, index, options, depth)?;
+        left = AST::binary(op_token, left, right);
     }
     return Ok(left);
 }
 
-fn parse_pow(tokens: &Vec<Token>, index: &mut usize) -> Result<AST, ParseError> {
-    let mut pows: Vec<AST> = vec![parse_unary(tokens, index)?];
-    loop {
-        match consume_token(tokens, index) {
-            Ok(&Token::BinOp(token)) => match token {
-                BinOp::Pow => (),
-                _ => {
-                    *index -= 1;
-                    break;
-                }
-            },
-            _ => {
-                *index -= 1;
-                break;
-            }
-        };
-        pows.push(parse_unary(tokens, index)?);
+fn parse_pow(
+    tokens: &[SpannedToken],
+    index: &mut usize,
+    options: &ParseOptions,
+    depth: usize,
+) -> Result<AST, ParseError> {
+    let mut pows: Vec<AST> = vec![parse_unary(tokens, index, options, depth)?];
+    while let Some(Token::BinOp(BinOp::Pow)) = peek_token(tokens, *index) {
+        *index += 1;
+        pows.push(parse_unary(tokens, index, options, depth)?);
     }
     let mut right = pows.pop().unwrap();
-    while !pows.is_empty() {
-        let left = pows.pop().unwrap();
-        right = AST::BinOp {
-            op: BinOp::Pow,
-            left: Box::new(left),
-            right: Box::new(right),
-        };
+    while let Some(left) = pows.pop() {
+        right = AST::binary(BinOp::Pow, left, right);
     }
     Ok(right)
 }
 
-fn parse_unary(tokens: &Vec<Token>, index: &mut usize) -> Result<AST, ParseError> {
-    return match tokens.get(*index) {
+fn parse_unary(
+    tokens: &[SpannedToken],
+    index: &mut usize,
+    options: &ParseOptions,
+    depth: usize,
+) -> Result<AST, ParseError> {
+    if depth > options.max_depth {
+        return Err(ParseError::NestingTooDeep {
+            span: tokens
+                .get(*index)
+                .map_or_else(|| end_of_input(tokens), |token| token.span),
+            limit: options.max_depth,
+        });
+    }
+    return match peek_token(tokens, *index) {
         Some(&Token::BinOp(BinOp::Sub)) => {
             *index += 1;
             Ok(AST::Function {
                 name: "-".to_string(),
-                args: vec![parse_unary(tokens, index)?],
+                args: vec![Rc::new(parse_unary(tokens, index, options, depth + 1)?)],
             })
         }
-        _ => parse_primary(tokens, index),
+        Some(&Token::Radical) => {
+            *index += 1;
+            Ok(AST::Function {
+                name: "sqrt".to_string(),
+                args: vec![Rc::new(parse_unary(tokens, index, options, depth + 1)?)],
+            })
+        }
+        _ => parse_primary(tokens, index, options, depth),
     };
 }
 
-fn parse_primary(tokens: &Vec<Token>, index: &mut usize) -> Result<AST, ParseError> {
-    return match consume_token(tokens, index)? {
+fn parse_primary(
+    tokens: &[SpannedToken],
+    index: &mut usize,
+    options: &ParseOptions,
+    depth: usize,
+) -> Result<AST, ParseError> {
+    let current = consume_token(tokens, index)?;
+    return match &current.token {
         Token::Literal(value) => Ok(AST::Literal { value: *value }),
         Token::SymbolicConstant(name) => Ok(AST::SymbolicConstant { name: name.clone() }),
         Token::Variable(name) => Ok(AST::Variable { name: name.clone() }),
-        Token::CloseParen => Err(ParseError::UnclosedParen),
-        Token::Comma => Err(ParseError::UnexpectedTokens),
+        Token::CloseParen | Token::Comma | Token::Equals => Err(unexpected(current)),
+        Token::Function(name)
+            if options.implicit_multiplication
+                && options.registry.get(name).is_none()
+                && !options.extra_functions.contains(name) =>
+        {
+            Err(ParseError::UnknownFunction {
+                span: current.span,
+                name: name.clone(),
+            })
+        }
         Token::Function(name) => {
-            match consume_token(tokens, index)? {
+            let open = consume_token(tokens, index)?;
+            match open.token {
                 Token::OpenParen => (),
-                _ => return Err(ParseError::MissingParen),
+                _ => return Err(unexpected(open)),
             };
-            let mut args = vec![parse_term(tokens, index)?];
-            loop {
-                match consume_token(tokens, index) {
-                    Ok(Token::Comma) => (),
-                    _ => {
-                        *index -= 1;
-                        break;
-                    }
-                }
-                args.push(parse_term(tokens, index)?);
+            let mut args = vec![Rc::new(parse_term(tokens, index, options, depth + 1)?)];
+            while let Some(Token::Comma) = peek_token(tokens, *index) {
+                *index += 1;
+                args.push(Rc::new(parse_term(tokens, index, options, depth + 1)?));
             }
-            match consume_token(tokens, index)? {
-                Token::CloseParen => (),
-                _ => return Err(ParseError::MissingParen),
-            };
+            expect_close_paren(tokens, index, open.span)?;
             Ok(AST::Function {
                 name: name.clone(),
                 args,
             })
         }
         Token::OpenParen => {
-            let arg = parse_term(tokens, index)?;
-            match consume_token(tokens, index)? {
-                Token::CloseParen => (),
-                _ => return Err(ParseError::MissingParen),
-            };
+            let arg = parse_term(tokens, index, options, depth + 1)?;
+            expect_close_paren(tokens, index, current.span)?;
             Ok(arg)
         }
-        Token::BinOp(_) => Err(ParseError::UnexpectedOperator),
+        Token::Radical => unreachable!("`√` is handled by parse_unary"),
+        Token::BinOp(operator) => Err(ParseError::UnexpectedOperator {
+            span: current.span,
+            operator: *operator,
+        }),
+    };
+}
+
+/// Consumes the `)` matching the `(` at `open`.
+fn expect_close_paren(
+    tokens: &[SpannedToken],
+    index: &mut usize,
+    open: Span,
+) -> Result<(), ParseError> {
+    return match tokens.get(*index) {
+        Some(SpannedToken {
+            token: Token::CloseParen,
+            ..
+        }) => {
+            *index += 1;
+            Ok(())
+        }
+        Some(token) => Err(ParseError::MissingParen {
+            span: token.span,
+            found: token.token.to_string(),
+        }),
+        None => Err(ParseError::UnclosedParen { span: open }),
     };
 }
 
-fn consume_token<'a>(tokens: &'a Vec<Token>, index: &mut usize) -> Result<&'a Token, ParseError> {
+fn unexpected(token: &SpannedToken) -> ParseError {
+    return ParseError::UnexpectedTokens {
+        span: token.span,
+        found: token.token.to_string(),
+    };
+}
+
+fn peek_token(tokens: &[SpannedToken], index: usize) -> Option<&Token> {
+    return tokens.get(index).map(|token| &token.token);
+}
+
+fn consume_token<'a>(
+    tokens: &'a [SpannedToken],
+    index: &mut usize,
+) -> Result<&'a SpannedToken, ParseError> {
     *index += 1;
-    return tokens.get(*index - 1).ok_or(ParseError::EndOfStream);
+    return tokens
+        .get(*index - 1)
+        .ok_or_else(|| ParseError::EndOfStream {
+            span: end_of_input(tokens),
+        });
+}
+
+/// An empty span just past the last token.
+fn end_of_input(tokens: &[SpannedToken]) -> Span {
+    let end = tokens.last().map_or(0, |token| token.span.end);
+    return Span { start: end, end };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lex;
+
+    fn parse_implicit(source: &str) -> Result<AST, ParseError> {
+        let options = ParseOptions {
+            implicit_multiplication: true,
+            ..ParseOptions::default()
+        };
+        return parse_with(&lex(source).unwrap(), &options);
+    }
+
+    #[test]
+    fn unknown_functions_are_errors_under_implicit_multiplication() {
+        assert!(matches!(
+            parse_implicit("2foo(x)"),
+            Err(ParseError::UnknownFunction { name, span }) if name == "foo" && span.start == 1
+        ));
+        assert!(parse_implicit("2x(x+1)").is_err());
+        assert_eq!(
+            parse_implicit("2x (x+1)").unwrap().to_string(),
+            "2 * x * (x + 1)"
+        );
+        assert_eq!(parse_implicit("2sin(x)").unwrap().to_string(), "2 * sin(x)");
+    }
+
+    #[test]
+    fn registered_functions_stay_calls() {
+        let mut registry = FunctionRegistry::with_builtins();
+        registry.register(
+            "sigmoid",
+            crate::FunctionDefinition::unary(|x| Ok(1. / (1. + (-x).exp()))),
+        );
+        let options = ParseOptions {
+            implicit_multiplication: true,
+            registry: &registry,
+            ..ParseOptions::default()
+        };
+        let ast = parse_with(&lex("2sigmoid(x)").unwrap(), &options).unwrap();
+        assert_eq!(ast.to_string(), "2 * sigmoid(x)");
+    }
+
+    #[test]
+    fn extra_functions_stay_calls() {
+        let options = ParseOptions {
+            implicit_multiplication: true,
+            extra_functions: vec!["int".to_string()],
+            ..ParseOptions::default()
+        };
+        let ast = parse_with(&lex("int(2x (x+1), x)").unwrap(), &options).unwrap();
+        assert_eq!(ast.to_string(), "int(2 * x * (x + 1), x)");
+    }
+
+    #[test]
+    fn unknown_function_without_implicit_multiplication_is_a_call() {
+        let ast = parse(&lex("f(x)").unwrap()).unwrap();
+        assert_eq!(ast.to_string(), "f(x)");
+    }
 }
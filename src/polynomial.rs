@@ -0,0 +1,40 @@
+//! Arithmetic on polynomials given by their coefficients, lowest power first. The coefficients
+//! are numbers where the integrator and limits need them and expressions where the solver does.
+
+use std::ops::{Add, Mul, Neg, Sub};
+
+/// The sum or difference of two polynomials.
+pub(crate) fn add_polynomials<T>(a: &[T], b: &[T], subtract: bool) -> Vec<T>
+where
+    T: Clone + Add<Output = T> + Sub<Output = T> + Neg<Output = T>,
+{
+    return (0..a.len().max(b.len()))
+        .map(|power| match (a.get(power), b.get(power)) {
+            (Some(a), Some(b)) if subtract => a.clone() - b.clone(),
+            (Some(a), Some(b)) => a.clone() + b.clone(),
+            (Some(a), None) => a.clone(),
+            (None, Some(b)) if subtract => -b.clone(),
+            (None, Some(b)) => b.clone(),
+            (None, None) => unreachable!(),
+        })
+        .collect();
+}
+
+/// The product of two polynomials. Each coefficient is the sum of the products that contribute
+/// to it, without a zero to start from, so expression coefficients do not pick up `0 + ...`.
+pub(crate) fn multiply_polynomials<T>(a: &[T], b: &[T]) -> Vec<T>
+where
+    T: Clone + Add<Output = T> + Mul<Output = T>,
+{
+    let mut product: Vec<Option<T>> = vec![None; a.len() + b.len() - 1];
+    for (i, a) in a.iter().enumerate() {
+        for (j, b) in b.iter().enumerate() {
+            let term = a.clone() * b.clone();
+            product[i + j] = Some(match product[i + j].take() {
+                Some(sum) => sum + term,
+                None => term,
+            });
+        }
+    }
+    return product.into_iter().map(Option::unwrap).collect();
+}
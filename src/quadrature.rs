@@ -0,0 +1,477 @@
+use std::collections::HashMap;
+
+use crate::{
+    ast::AST, functions::FunctionRegistry, integrator::IntegrationError,
+    simplifier::EvaluationError, Error,
+};
+
+/// Quadrature stops once the estimated error is below `ABSOLUTE_TOLERANCE` or
+/// `RELATIVE_TOLERANCE` times the value, whichever is larger.
+const ABSOLUTE_TOLERANCE: f64 = 1e-12;
+const RELATIVE_TOLERANCE: f64 = 1e-10;
+/// Most subintervals adaptive quadrature splits the range into before giving up on the
+/// tolerance and returning its best estimate.
+const MAX_SEGMENTS: usize = 1000;
+/// When quadrature gives up with an error estimate above this fraction of the value (or of 1,
+/// if the value is smaller), the integral is taken to diverge.
+const DIVERGENCE_THRESHOLD: f64 = 1e-3;
+/// How many points where the integrand is not finite the interval is split at before the
+/// integral is taken to diverge.
+const MAX_BREAKPOINTS: usize = 16;
+/// How close, relative to the length of the interval, a point where the integrand is not
+/// finite may be to an end before it is taken to be the singularity at that end rather than a
+/// new one to split at.
+const BREAKPOINT_MARGIN: f64 = 1e-9;
+/// How many samples [`poles`] looks for sign changes between.
+const POLE_SAMPLES: usize = 256;
+
+/// Nodes of the 15-point Kronrod rule on `[-1, 1]`, from the outside in. The odd-indexed ones
+/// are also the nodes of the embedded 7-point Gauss rule.
+const KRONROD_NODES: [f64; 8] = [
+    0.9914553711208126,
+    0.9491079123427585,
+    0.8648644233597691,
+    0.7415311855993945,
+    0.5860872354676911,
+    0.4058451513773972,
+    0.20778495500789848,
+    0.,
+];
+const KRONROD_WEIGHTS: [f64; 8] = [
+    0.022935322010529224,
+    0.06309209262997856,
+    0.10479001032225019,
+    0.14065325971552592,
+    0.1690047266392679,
+    0.19035057806478542,
+    0.20443294007529889,
+    0.20948214108472782,
+];
+const GAUSS_WEIGHTS: [f64; 4] = [
+    0.1294849661688697,
+    0.27970539148927664,
+    0.3818300505051189,
+    0.4179591836734694,
+];
+
+/// The value of a definite integral and how it was found.
+#[derive(Debug, Clone)]
+pub struct DefiniteIntegral {
+    pub value: f64,
+    /// Estimated absolute error of `value`.
+    pub error: f64,
+    /// The antiderivative `value` was computed from, or `None` if it came from quadrature.
+    pub antiderivative: Option<AST>,
+}
+
+/// The integral of `expr` with respect to `var` from `a` to `b`.
+///
+/// When both bounds are finite and [`AST::integrate`] finds an antiderivative `F` that is
+/// continuous on the interval, the exact `F(b) - F(a)` is returned. Otherwise the integral is
+/// computed by adaptive 15-point Gauss–Kronrod quadrature, with the difference from the
+/// embedded 7-point Gauss rule as the error estimate. Infinite bounds are mapped onto a finite
+/// interval first, and the interval is split at points where the integrand is not finite. An
+/// integral that does not converge is an [`IntegrationError::Divergent`], and a NaN bound is an
+/// [`IntegrationError::NanBound`].
+pub fn integrate_definite(
+    expr: &AST,
+    var: &str,
+    a: f64,
+    b: f64,
+) -> Result<DefiniteIntegral, Error> {
+    return integrate_definite_with(expr, var, a, b, FunctionRegistry::builtin());
+}
+
+/// [`integrate_definite`] using the functions in `registry`.
+pub fn integrate_definite_with(
+    expr: &AST,
+    var: &str,
+    a: f64,
+    b: f64,
+    registry: &FunctionRegistry,
+) -> Result<DefiniteIntegral, Error> {
+    if a.is_nan() || b.is_nan() {
+        return Err(IntegrationError::NanBound.into());
+    }
+    let mut env = HashMap::new();
+    let mut integrand = |x: f64| -> Result<f64, Error> {
+        env.insert(var.to_string(), x);
+        let value = match expr.evaluate_with(&env, registry) {
+            Err(EvaluationError::ZeroDivisionError) => f64::INFINITY,
+            value => value?,
+        };
+        if !value.is_finite() {
+            return Err(IntegrationError::NotFinite { at: x }.into());
+        }
+        return Ok(value);
+    };
+    if a.is_finite() && b.is_finite() {
+        if let Some(integral) = integrate_symbolically(&mut integrand, expr, var, a, b, registry) {
+            return Ok(integral);
+        }
+    }
+    let (value, error) = if a > b {
+        let (value, error) = integrate_piecewise(&mut integrand, b, a)?;
+        (-value, error)
+    } else {
+        integrate_piecewise(&mut integrand, a, b)?
+    };
+    return Ok(DefiniteIntegral {
+        value,
+        error,
+        antiderivative: None,
+    });
+}
+
+/// [`integrate_numerically`] over `[a, b]` with `a <= b`, split at the [`poles`] of `f` and at
+/// every other point where it turns out not to be finite, so that these become ends of the
+/// pieces, which quadrature never samples. A piece whose integral does not converge at such an
+/// end makes the whole integral diverge.
+fn integrate_piecewise<F>(f: &mut F, a: f64, b: f64) -> Result<(f64, f64), Error>
+where
+    F: FnMut(f64) -> Result<f64, Error>,
+{
+    let mut ends = vec![a];
+    if a.is_finite() && b.is_finite() {
+        ends.extend(poles(f, a, b)?);
+    }
+    ends.push(b);
+    let mut pending: Vec<(f64, f64)> = ends.windows(2).rev().map(|w| (w[0], w[1])).collect();
+    let mut breakpoints = 0;
+    let (mut value, mut error) = (0., 0.);
+    while let Some((a, b)) = pending.pop() {
+        match integrate_numerically(f, a, b) {
+            Ok((piece, piece_error)) => {
+                value += piece;
+                error += piece_error;
+            }
+            Err(Error::Integration(IntegrationError::NotFinite { at }))
+                if at.is_finite() && snap(at, a, b) == at && breakpoints < MAX_BREAKPOINTS =>
+            {
+                breakpoints += 1;
+                pending.push((at, b));
+                pending.push((a, at));
+            }
+            Err(Error::Integration(
+                IntegrationError::NotFinite { at } | IntegrationError::Divergent { near: at },
+            )) => {
+                return Err(IntegrationError::Divergent {
+                    near: snap(at, a, b),
+                }
+                .into())
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    return Ok((value, error));
+}
+
+/// `at`, or the end of `[a, b]` that it is within [`BREAKPOINT_MARGIN`] of.
+fn snap(at: f64, a: f64, b: f64) -> f64 {
+    if !at.is_finite() {
+        return at;
+    }
+    let scale = if (b - a).is_finite() {
+        b - a
+    } else {
+        1. + at.abs()
+    };
+    let margin = BREAKPOINT_MARGIN * scale;
+    if (at - a).abs() <= margin {
+        return a;
+    }
+    if (b - at).abs() <= margin {
+        return b;
+    }
+    return at;
+}
+
+/// Points inside `[a, b]` where `f` is not finite or changes sign by blowing up rather than by
+/// passing through zero. Sign changes between [`POLE_SAMPLES`] samples are narrowed by
+/// bisection and kept when `|f|` grows as the bracket shrinks. Quadrature can step right over
+/// such a pole when the two sides cancel, as for `tan(x)` around `pi / 2`, so a pole that is
+/// not integrable is reported as an [`IntegrationError::Divergent`] straight away.
+fn poles<F>(f: &mut F, a: f64, b: f64) -> Result<Vec<f64>, Error>
+where
+    F: FnMut(f64) -> Result<f64, Error>,
+{
+    let mut poles = Vec::new();
+    let mut previous: Option<(f64, f64)> = None;
+    for index in 1..POLE_SAMPLES {
+        let x = a + (b - a) * index as f64 / POLE_SAMPLES as f64;
+        let y = match f(x) {
+            Ok(y) => y,
+            Err(Error::Integration(IntegrationError::NotFinite { .. })) => {
+                poles.push(x);
+                previous = None;
+                continue;
+            }
+            Err(_) => {
+                previous = None;
+                continue;
+            }
+        };
+        if let Some((x0, y0)) = previous {
+            if y0 * y < 0. {
+                poles.extend(narrow_pole(f, (x0, y0), (x, y)));
+            }
+        }
+        previous = Some((x, y));
+    }
+    for pole in &poles {
+        if !is_integrable_at(f, *pole, b - a) {
+            return Err(IntegrationError::Divergent { near: *pole }.into());
+        }
+    }
+    return Ok(poles);
+}
+
+/// Whether `f` blows up slower than `1 / |x - pole|` on both sides of `pole`, judged by how
+/// `|f(x)| * |x - pole|` changes between two distances from it; it tends to zero exactly when
+/// the singularity is integrable.
+fn is_integrable_at<F>(f: &mut F, pole: f64, scale: f64) -> bool
+where
+    F: FnMut(f64) -> Result<f64, Error>,
+{
+    let (near, far) = (1e-12 * scale, 1e-6 * scale);
+    for side in [-1., 1.] {
+        let decay = match (f(pole + side * near), f(pole + side * far)) {
+            (Ok(at_near), Ok(at_far)) => (at_near * near).abs() / (at_far * far).abs(),
+            _ => return false,
+        };
+        if decay.is_nan() || decay >= 0.5 {
+            return false;
+        }
+    }
+    return true;
+}
+
+/// Bisects the sign change between `low` and `high` and returns where it is if `f` blows up
+/// there.
+fn narrow_pole<F>(f: &mut F, low: (f64, f64), high: (f64, f64)) -> Option<f64>
+where
+    F: FnMut(f64) -> Result<f64, Error>,
+{
+    let start = low.1.abs().max(high.1.abs());
+    let ((mut x0, mut y0), (mut x1, mut y1)) = (low, high);
+    loop {
+        let middle = (x0 + x1) / 2.;
+        if middle <= x0 || middle >= x1 {
+            break;
+        }
+        match f(middle) {
+            Ok(0.) => return None,
+            Ok(y) if y.signum() == y0.signum() => (x0, y0) = (middle, y),
+            Ok(y) => (x1, y1) = (middle, y),
+            Err(_) => return Some(middle),
+        }
+    }
+    if y0.abs().min(y1.abs()) > start {
+        return Some((x0 + x1) / 2.);
+    }
+    return None;
+}
+
+/// `F(b) - F(a)` for the antiderivative `F` of `f`, or `None` if there is no antiderivative or
+/// it cannot be trusted across `[a, b]`: `F(b) - F(a)` is only the integral when neither `f`
+/// nor `F` has a pole in between, which `ln(abs(x))` for `1 / x` over `[-1, 1]` does.
+fn integrate_symbolically<F>(
+    f: &mut F,
+    expr: &AST,
+    var: &str,
+    a: f64,
+    b: f64,
+    registry: &FunctionRegistry,
+) -> Option<DefiniteIntegral>
+where
+    F: FnMut(f64) -> Result<f64, Error>,
+{
+    let antiderivative = expr.integrate_with(var, registry).ok()?;
+    let mut env = HashMap::new();
+    let mut at = |x: f64| -> Result<f64, Error> {
+        env.insert(var.to_string(), x);
+        let value = antiderivative.evaluate_with(&env, registry)?;
+        if !value.is_finite() {
+            return Err(IntegrationError::NotFinite { at: x }.into());
+        }
+        return Ok(value);
+    };
+    let (lower, upper) = (at(a).ok()?, at(b).ok()?);
+    let (low, high) = (a.min(b), a.max(b));
+    if !poles(&mut at, low, high).ok()?.is_empty() || !poles(f, low, high).ok()?.is_empty() {
+        return None;
+    }
+    return Some(DefiniteIntegral {
+        value: upper - lower,
+        error: f64::EPSILON * (lower.abs() + upper.abs()),
+        antiderivative: Some(antiderivative),
+    });
+}
+
+/// Adaptive quadrature of `f` over `[a, b]` with `a <= b`, returning the value and its error
+/// estimate. Infinite bounds are handled by a change of variable `x = to_x(t)`.
+fn integrate_numerically<F>(f: &mut F, a: f64, b: f64) -> Result<(f64, f64), Error>
+where
+    F: FnMut(f64) -> Result<f64, Error>,
+{
+    let to_x = |t: f64| -> f64 {
+        return match (a.is_finite(), b.is_finite()) {
+            (true, true) => t,
+            // Maps [0, 1) onto [a, ∞).
+            (true, false) => a + t / (1. - t),
+            // Maps (0, 1] onto (-∞, b].
+            (false, true) => b - (1. - t) / t,
+            // Maps (-1, 1) onto the whole line.
+            (false, false) => t / (1. - t * t),
+        };
+    };
+    let result = match (a.is_finite(), b.is_finite()) {
+        (true, true) => adaptive(f, a, b),
+        (true, false) => adaptive(&mut |t| Ok(f(to_x(t))? / ((1. - t) * (1. - t))), 0., 1.),
+        (false, true) => adaptive(&mut |t| Ok(f(to_x(t))? / (t * t)), 0., 1.),
+        (false, false) => adaptive(
+            &mut |t| {
+                let scale = 1. - t * t;
+                Ok(f(to_x(t))? * (1. + t * t) / (scale * scale))
+            },
+            -1.,
+            1.,
+        ),
+    };
+    return result.map_err(|err| match err {
+        Error::Integration(IntegrationError::Divergent { near }) => {
+            IntegrationError::Divergent { near: to_x(near) }.into()
+        }
+        err => err,
+    });
+}
+
+/// A subinterval with its Kronrod estimate and error.
+struct Segment {
+    a: f64,
+    b: f64,
+    value: f64,
+    error: f64,
+}
+
+/// Repeatedly bisects the segment with the largest error until the total error is within
+/// tolerance or there are [`MAX_SEGMENTS`] of them, and then returns the best estimate. A
+/// worst segment too short to split, or giving up with an error above
+/// [`DIVERGENCE_THRESHOLD`], is an [`IntegrationError::Divergent`] there instead.
+fn adaptive<F>(f: &mut F, a: f64, b: f64) -> Result<(f64, f64), Error>
+where
+    F: FnMut(f64) -> Result<f64, Error>,
+{
+    let mut segments = vec![gauss_kronrod(f, a, b)?];
+    loop {
+        let value: f64 = segments.iter().map(|segment| segment.value).sum();
+        let error: f64 = segments.iter().map(|segment| segment.error).sum();
+        if error <= ABSOLUTE_TOLERANCE.max(RELATIVE_TOLERANCE * value.abs()) {
+            return Ok((value, error));
+        }
+        let (index, worst) = segments
+            .iter()
+            .enumerate()
+            .max_by(|(_, x), (_, y)| x.error.total_cmp(&y.error))
+            .unwrap();
+        let middle = (worst.a + worst.b) / 2.;
+        if middle <= worst.a || middle >= worst.b {
+            return Err(IntegrationError::Divergent { near: middle }.into());
+        }
+        if segments.len() >= MAX_SEGMENTS {
+            if error > DIVERGENCE_THRESHOLD * value.abs().max(1.) {
+                return Err(IntegrationError::Divergent { near: middle }.into());
+            }
+            return Ok((value, error));
+        }
+        let (left, right) = (worst.a, worst.b);
+        segments[index] = gauss_kronrod(f, left, middle)?;
+        segments.push(gauss_kronrod(f, middle, right)?);
+    }
+}
+
+fn gauss_kronrod<F>(f: &mut F, a: f64, b: f64) -> Result<Segment, Error>
+where
+    F: FnMut(f64) -> Result<f64, Error>,
+{
+    let center = (a + b) / 2.;
+    let half_width = (b - a) / 2.;
+    let mut kronrod = 0.;
+    let mut gauss = 0.;
+    for (index, (node, weight)) in KRONROD_NODES.iter().zip(KRONROD_WEIGHTS).enumerate() {
+        let values = if *node == 0. {
+            f(center)?
+        } else {
+            f(center - half_width * node)? + f(center + half_width * node)?
+        };
+        kronrod += weight * values;
+        if index % 2 == 1 {
+            gauss += GAUSS_WEIGHTS[index / 2] * values;
+        }
+    }
+    return Ok(Segment {
+        a,
+        b,
+        value: kronrod * half_width,
+        error: ((kronrod - gauss) * half_width).abs(),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::integrate_definite;
+    use crate::{integrator::IntegrationError, parse_str, Error};
+
+    fn integral(source: &str, a: f64, b: f64) -> Result<f64, Error> {
+        let expr = parse_str(source).unwrap();
+        return integrate_definite(&expr, "x", a, b).map(|integral| integral.value);
+    }
+
+    fn diverges_near(source: &str, a: f64, b: f64) -> f64 {
+        return match integral(source, a, b) {
+            Err(Error::Integration(IntegrationError::Divergent { near })) => near,
+            result => panic!("expected {} to diverge, got {:?}", source, result),
+        };
+    }
+
+    #[test]
+    fn uses_the_antiderivative_when_it_agrees() {
+        let expr = parse_str("x^2 * sin(x)").unwrap();
+        let exact = integrate_definite(&expr, "x", 0., std::f64::consts::PI).unwrap();
+        assert!(exact.antiderivative.is_some());
+        assert!((exact.value - (std::f64::consts::PI.powi(2) - 4.)).abs() < 1e-12);
+        assert!((integral("1 / x", 2., 1.).unwrap() + 2f64.ln()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn prefers_the_antiderivative_to_quadrature() {
+        // Quadrature alone takes this to diverge at 0.
+        let expr = parse_str("x^-0.999").unwrap();
+        let exact = integrate_definite(&expr, "x", 0., 1.).unwrap();
+        assert!(exact.antiderivative.is_some());
+        assert!((exact.value - 1000.).abs() < 1e-9);
+        assert!(matches!(
+            integral("x", f64::NAN, 1.),
+            Err(Error::Integration(IntegrationError::NanBound))
+        ));
+    }
+
+    #[test]
+    fn singularities_inside_the_interval_diverge() {
+        assert_eq!(diverges_near("1 / x", -1., 1.), 0.);
+        assert_eq!(diverges_near("1 / x^2", -1., 1.), 0.);
+        assert!(diverges_near("1 / x^2", -1., 2.).abs() < 1e-9);
+        assert!((diverges_near("tan(x)", 0., 3.) - std::f64::consts::FRAC_PI_2).abs() < 1e-12);
+        assert_eq!(diverges_near("1 / x", 0., 1.), 0.);
+        assert_eq!(diverges_near("1 / x", 1., f64::INFINITY), f64::INFINITY);
+    }
+
+    #[test]
+    fn integrable_singularities_converge() {
+        assert!((integral("1 / sqrt(x)", 0., 1.).unwrap() - 2.).abs() < 1e-8);
+        assert!((integral("1 / sqrt(abs(x))", -1., 1.).unwrap() - 4.).abs() < 1e-8);
+        assert!((integral("ln(x)", 0., 1.).unwrap() + 1.).abs() < 1e-8);
+        let gaussian = integral("exp(-(x^2))", f64::NEG_INFINITY, f64::INFINITY).unwrap();
+        assert!((gaussian - std::f64::consts::PI.sqrt()).abs() < 1e-10);
+    }
+}
@@ -0,0 +1,472 @@
+use std::{collections::HashMap, error, fmt};
+
+use crate::{ast::AST, functions::FunctionRegistry, multivariate::jacobian_with, Error};
+
+#[derive(Debug)]
+pub enum RootError {
+    /// Neither Newton's method nor the bracketing fallback converged within the iteration
+    /// limit, or no sign change could be found to bracket a root.
+    NoConvergence { iterations: usize },
+    /// The sign change found near `near` is a pole rather than a root: `|f|` grows as the
+    /// interval around it shrinks, or stays large where the iteration converged.
+    Pole { near: f64 },
+    /// The Jacobian of a system is singular at `point`, so Newton's method cannot take a step.
+    SingularJacobian { point: Vec<f64> },
+    /// A system needs as many equations as unknowns, and a starting value for each unknown.
+    DimensionMismatch {
+        equations: usize,
+        unknowns: usize,
+        starting_values: usize,
+    },
+}
+impl error::Error for RootError {}
+
+impl fmt::Display for RootError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            RootError::NoConvergence { iterations } => {
+                write!(formatter, "no root found after {} iterations", iterations)
+            }
+            RootError::Pole { near } => {
+                write!(
+                    formatter,
+                    "the sign change near {} is a pole, not a root",
+                    near
+                )
+            }
+            RootError::SingularJacobian { point } => {
+                write!(formatter, "the Jacobian is singular at {:?}", point)
+            }
+            RootError::DimensionMismatch {
+                equations,
+                unknowns,
+                starting_values,
+            } => write!(
+                formatter,
+                "{} equations, {} unknowns and {} starting values do not match",
+                equations, unknowns, starting_values
+            ),
+        };
+    }
+}
+
+/// Settings for [`find_root_with`] and [`find_root_system_with`].
+#[derive(Debug, Clone)]
+pub struct RootOptions {
+    /// The iteration stops once a step is smaller than this, relative to the size of the
+    /// current estimate when that is above `1`.
+    pub tolerance: f64,
+    /// How many iterations each method may take before giving up.
+    pub max_iterations: usize,
+}
+
+impl Default for RootOptions {
+    fn default() -> Self {
+        return RootOptions {
+            tolerance: 1e-12,
+            max_iterations: 100,
+        };
+    }
+}
+
+/// How many times the search for a sign change doubles its distance from the starting point.
+const MAX_BRACKET_EXPANSIONS: usize = 60;
+/// How many times a Newton step for a system is halved while it fails to reduce the residual.
+const MAX_STEP_HALVINGS: usize = 30;
+/// How many times a bracket is bisected before Brent's method to tell a root from a pole.
+const POLE_BISECTIONS: usize = 8;
+/// The largest `|f|` accepted at a root, relative to `|f|` at the ends of its bracket.
+const MAX_RESIDUAL: f64 = 1e-6;
+
+/// A root of `expr` in `var` near `x0`, with the default [`RootOptions`].
+///
+/// Uses Newton's method with the symbolic derivative. If that fails to converge, e.g. because
+/// the derivative vanishes or the iterates run away, it falls back to Brent's method on an
+/// interval where `expr` changes sign: either one Newton stepped across or one found by
+/// searching outwards from `x0`.
+pub fn find_root(expr: &AST, var: &str, x0: f64) -> Result<f64, Error> {
+    return find_root_with(
+        expr,
+        var,
+        x0,
+        &RootOptions::default(),
+        FunctionRegistry::builtin(),
+    );
+}
+
+/// [`find_root`] with the given options, using the functions in `registry`.
+pub fn find_root_with(
+    expr: &AST,
+    var: &str,
+    x0: f64,
+    options: &RootOptions,
+    registry: &FunctionRegistry,
+) -> Result<f64, Error> {
+    let mut derivative = expr.differentiate_wrt_with(var, registry)?;
+    derivative.simplify_with(registry)?;
+    let mut env = HashMap::new();
+    let mut f = |x: f64| -> Result<f64, Error> {
+        env.insert(var.to_string(), x);
+        return Ok(expr.evaluate_with(&env, registry)?);
+    };
+    let f_prime = |x: f64| -> Option<f64> {
+        let env = HashMap::from([(var.to_string(), x)]);
+        return derivative.evaluate_with(&env, registry).ok();
+    };
+    let mut bracket: Option<(f64, f64, f64, f64)> = None;
+    let mut x = x0;
+    let mut fx = f(x)?;
+    let max_residual = MAX_RESIDUAL * fx.abs().max(1.);
+    for _ in 0..options.max_iterations {
+        if fx == 0. {
+            return Ok(x);
+        }
+        let step = match f_prime(x) {
+            Some(slope) if slope != 0. => fx / slope,
+            _ => break,
+        };
+        let next = x - step;
+        let f_next = match f(next) {
+            Ok(value) if next.is_finite() && value.is_finite() => value,
+            _ => break,
+        };
+        // Keep the narrowest interval Newton has stepped across, since diverging iterates
+        // cross the root with ever larger steps.
+        let narrower = match bracket {
+            Some((a, _, b, _)) => (next - x).abs() < (b - a).abs(),
+            None => true,
+        };
+        if f_next.signum() != fx.signum() && narrower {
+            bracket = Some((x, fx, next, f_next));
+        }
+        if step.abs() <= options.tolerance * next.abs().max(1.) {
+            if f_next.abs() <= max_residual {
+                return Ok(next);
+            }
+            // Converged somewhere `f` is not small, so fall back to bracketing.
+            break;
+        }
+        x = next;
+        fx = f_next;
+    }
+    let bracket = match bracket {
+        Some(bracket) => bracket,
+        None => find_bracket(&mut f, x0)?,
+    };
+    return brent(&mut f, bracket, options);
+}
+
+/// A root of `expr` in `var` between `a` and `b`, where `expr` changes sign, by Brent's method.
+pub(crate) fn find_root_between(
+    expr: &AST,
+    var: &str,
+    (a, b): (f64, f64),
+    options: &RootOptions,
+    registry: &FunctionRegistry,
+) -> Result<f64, Error> {
+    let mut env = HashMap::new();
+    let mut f = |x: f64| -> Result<f64, Error> {
+        env.insert(var.to_string(), x);
+        return Ok(expr.evaluate_with(&env, registry)?);
+    };
+    let (fa, fb) = (f(a)?, f(b)?);
+    return brent(&mut f, (a, fa, b, fb), options);
+}
+
+/// Searches for a sign change on both sides of `x0`, doubling the distance each time.
+fn find_bracket<F>(f: &mut F, x0: f64) -> Result<(f64, f64, f64, f64), Error>
+where
+    F: FnMut(f64) -> Result<f64, Error>,
+{
+    let start = f(x0)?;
+    let mut distance = 0.01 * x0.abs().max(1.);
+    let (mut left, mut right) = ((x0, start), (x0, start));
+    for _ in 0..MAX_BRACKET_EXPANSIONS {
+        for (side, previous) in [(-1., &mut left), (1., &mut right)] {
+            let x = x0 + side * distance;
+            let fx = match f(x) {
+                Ok(value) if value.is_finite() => value,
+                // Outside the domain, e.g. `ln(x)` for `x <= 0`.
+                _ => continue,
+            };
+            if fx.signum() != previous.1.signum() {
+                return Ok((previous.0, previous.1, x, fx));
+            }
+            *previous = (x, fx);
+        }
+        distance *= 2.;
+    }
+    return Err(RootError::NoConvergence {
+        iterations: MAX_BRACKET_EXPANSIONS,
+    }
+    .into());
+}
+
+/// Bisects a sign-changing interval a few times, keeping the half that changes sign, and
+/// rejects it as a pole if `|f|` at both ends has grown beyond its value at the original ends,
+/// as it does for `1/x` around `0`.
+fn narrow<F>(
+    f: &mut F,
+    (mut a, mut fa, mut b, mut fb): (f64, f64, f64, f64),
+) -> Result<(f64, f64, f64, f64), Error>
+where
+    F: FnMut(f64) -> Result<f64, Error>,
+{
+    let scale = fa.abs().max(fb.abs());
+    for _ in 0..POLE_BISECTIONS {
+        let middle = a + (b - a) / 2.;
+        let f_middle = match f(middle) {
+            Ok(value) if value.is_finite() => value,
+            _ => return Err(RootError::Pole { near: middle }.into()),
+        };
+        if f_middle == 0. {
+            return Ok((middle, f_middle, middle, f_middle));
+        }
+        if f_middle.signum() == fa.signum() {
+            (a, fa) = (middle, f_middle);
+        } else {
+            (b, fb) = (middle, f_middle);
+        }
+    }
+    if fa.abs().min(fb.abs()) > scale {
+        return Err(RootError::Pole {
+            near: a + (b - a) / 2.,
+        }
+        .into());
+    }
+    return Ok((a, fa, b, fb));
+}
+
+/// Brent's method on an interval `(a, f(a), b, f(b))` where `f` changes sign: inverse
+/// quadratic interpolation or the secant method when they make good progress, bisection
+/// otherwise.
+///
+/// A sign change across a pole also converges, so the interval is checked with [`narrow`]
+/// first and the result is only accepted if `|f|` there is small.
+fn brent<F>(f: &mut F, bracket: (f64, f64, f64, f64), options: &RootOptions) -> Result<f64, Error>
+where
+    F: FnMut(f64) -> Result<f64, Error>,
+{
+    let max_residual = MAX_RESIDUAL * bracket.1.abs().max(bracket.3.abs()).max(1.);
+    let (mut a, mut fa, mut b, mut fb) = narrow(f, bracket)?;
+    let (mut c, mut fc) = (b, fb);
+    let mut previous_step = b - a;
+    let mut step = previous_step;
+    for _ in 0..options.max_iterations {
+        if fb == 0. {
+            return Ok(b);
+        }
+        if fb.signum() == fc.signum() {
+            (c, fc) = (a, fa);
+            step = b - a;
+            previous_step = step;
+        }
+        if fc.abs() < fb.abs() {
+            (a, fa, b, fb, c, fc) = (b, fb, c, fc, b, fb);
+        }
+        let tolerance = 2. * f64::EPSILON * b.abs() + options.tolerance / 2.;
+        let middle = (c - b) / 2.;
+        if middle.abs() <= tolerance {
+            if fb.abs() > max_residual {
+                return Err(RootError::Pole { near: b }.into());
+            }
+            return Ok(b);
+        }
+        if previous_step.abs() >= tolerance && fa.abs() > fb.abs() {
+            let s = fb / fa;
+            let (mut p, mut q) = if a == c {
+                // Secant step.
+                (2. * middle * s, 1. - s)
+            } else {
+                // Inverse quadratic interpolation.
+                let (q, r) = (fa / fc, fb / fc);
+                (
+                    s * (2. * middle * q * (q - r) - (b - a) * (r - 1.)),
+                    (q - 1.) * (r - 1.) * (s - 1.),
+                )
+            };
+            if p > 0. {
+                q = -q;
+            } else {
+                p = -p;
+            }
+            if 2. * p < (3. * middle * q - (tolerance * q).abs()).min((previous_step * q).abs()) {
+                previous_step = step;
+                step = p / q;
+            } else {
+                step = middle;
+                previous_step = step;
+            }
+        } else {
+            step = middle;
+            previous_step = step;
+        }
+        (a, fa) = (b, fb);
+        b += if step.abs() > tolerance {
+            step
+        } else {
+            tolerance.copysign(middle)
+        };
+        fb = f(b)?;
+    }
+    return Err(RootError::NoConvergence {
+        iterations: options.max_iterations,
+    }
+    .into());
+}
+
+/// A common root of `exprs` in `vars` near `x0`, with the default [`RootOptions`].
+///
+/// Uses Newton's method with the symbolic [`jacobian`](crate::jacobian), halving steps that
+/// do not reduce the residual.
+pub fn find_root_system(exprs: &[AST], vars: &[&str], x0: &[f64]) -> Result<Vec<f64>, Error> {
+    return find_root_system_with(
+        exprs,
+        vars,
+        x0,
+        &RootOptions::default(),
+        FunctionRegistry::builtin(),
+    );
+}
+
+/// [`find_root_system`] with the given options, using the functions in `registry`.
+pub fn find_root_system_with(
+    exprs: &[AST],
+    vars: &[&str],
+    x0: &[f64],
+    options: &RootOptions,
+    registry: &FunctionRegistry,
+) -> Result<Vec<f64>, Error> {
+    if exprs.len() != vars.len() || vars.len() != x0.len() {
+        return Err(RootError::DimensionMismatch {
+            equations: exprs.len(),
+            unknowns: vars.len(),
+            starting_values: x0.len(),
+        }
+        .into());
+    }
+    let jacobian = jacobian_with(exprs, vars, registry)?;
+    let evaluate = |exprs: &[AST], x: &[f64]| -> Result<Vec<f64>, Error> {
+        let env: HashMap<String, f64> = vars
+            .iter()
+            .map(|var| var.to_string())
+            .zip(x.iter().copied())
+            .collect();
+        return exprs
+            .iter()
+            .map(|expr| Ok(expr.evaluate_with(&env, registry)?))
+            .collect();
+    };
+    let mut x = x0.to_vec();
+    let mut residual = evaluate(exprs, &x)?;
+    for _ in 0..options.max_iterations {
+        let mut matrix = Vec::with_capacity(jacobian.len());
+        for row in &jacobian {
+            matrix.push(evaluate(row, &x)?);
+        }
+        let negated: Vec<f64> = residual.iter().map(|value| -value).collect();
+        let mut step = match solve_linear(matrix, negated) {
+            Some(step) => step,
+            None => return Err(RootError::SingularJacobian { point: x }.into()),
+        };
+        let norm = |values: &[f64]| values.iter().map(|value| value * value).sum::<f64>();
+        let mut halvings = 0;
+        let (next, next_residual) = loop {
+            let next: Vec<f64> = x.iter().zip(&step).map(|(x, dx)| x + dx).collect();
+            let candidate = evaluate(exprs, &next);
+            let improved = match &candidate {
+                Ok(candidate) => norm(candidate) <= norm(&residual),
+                Err(_) => false,
+            };
+            if improved || halvings == MAX_STEP_HALVINGS {
+                break (next, candidate?);
+            }
+            step.iter_mut().for_each(|dx| *dx /= 2.);
+            halvings += 1;
+        };
+        let converged = x
+            .iter()
+            .zip(&step)
+            .all(|(x, dx)| dx.abs() <= options.tolerance * x.abs().max(1.));
+        x = next;
+        residual = next_residual;
+        if converged || residual.iter().all(|value| *value == 0.) {
+            return Ok(x);
+        }
+    }
+    return Err(RootError::NoConvergence {
+        iterations: options.max_iterations,
+    }
+    .into());
+}
+
+/// Solves `matrix * x = rhs` by Gaussian elimination with partial pivoting, or returns `None`
+/// if the matrix is singular.
+fn solve_linear(mut matrix: Vec<Vec<f64>>, mut rhs: Vec<f64>) -> Option<Vec<f64>> {
+    let n = rhs.len();
+    for column in 0..n {
+        let pivot = (column..n).max_by(|i, j| {
+            matrix[*i][column]
+                .abs()
+                .total_cmp(&matrix[*j][column].abs())
+        })?;
+        if matrix[pivot][column].abs() < f64::EPSILON || !matrix[pivot][column].is_finite() {
+            return None;
+        }
+        matrix.swap(column, pivot);
+        rhs.swap(column, pivot);
+        let (above, below) = matrix.split_at_mut(column + 1);
+        let pivot_row = &above[column];
+        for (offset, row) in below.iter_mut().enumerate() {
+            let factor = row[column] / pivot_row[column];
+            for (entry, pivot_entry) in row[column..].iter_mut().zip(&pivot_row[column..]) {
+                *entry -= factor * pivot_entry;
+            }
+            rhs[column + 1 + offset] -= factor * rhs[column];
+        }
+    }
+    let mut x = vec![0.; n];
+    for row in (0..n).rev() {
+        let sum: f64 = (row + 1..n).map(|k| matrix[row][k] * x[k]).sum();
+        x[row] = (rhs[row] - sum) / matrix[row][row];
+    }
+    return Some(x);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_root, find_root_system, RootError};
+    use crate::{parse_str, Error};
+
+    #[test]
+    fn finds_roots() {
+        let cubic = parse_str("x^3 - 2*x - 5").unwrap();
+        let root = find_root(&cubic, "x", 2.).unwrap();
+        assert!((root - 2.0945514815423265).abs() < 1e-12);
+        // The derivative vanishes at the start, so this needs the bracketing fallback.
+        let shifted = parse_str("x^3 - 8").unwrap();
+        assert!((find_root(&shifted, "x", 0.).unwrap() - 2.).abs() < 1e-12);
+        let cosine = parse_str("cos(x) - x").unwrap();
+        assert!((find_root(&cosine, "x", 1.).unwrap() - 0.7390851332151607).abs() < 1e-12);
+    }
+
+    #[test]
+    fn poles_are_not_roots() {
+        for source in ["1/x", "1/(x - 0.3)", "1/(x^2 - 4)", "exp(x)/(x - 3)"] {
+            let expr = parse_str(source).unwrap();
+            match find_root(&expr, "x", 1.) {
+                Err(Error::Root(RootError::Pole { .. })) => {}
+                other => panic!("{}: expected a pole, got {:?}", source, other),
+            }
+        }
+    }
+
+    #[test]
+    fn solves_systems() {
+        let circle = parse_str("x^2 + y^2 - 4").unwrap();
+        let line = parse_str("x - y").unwrap();
+        let root = find_root_system(&[circle, line], &["x", "y"], &[1., 0.5]).unwrap();
+        let expected = 2f64.sqrt();
+        assert!((root[0] - expected).abs() < 1e-12 && (root[1] - expected).abs() < 1e-12);
+    }
+}
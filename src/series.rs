@@ -0,0 +1,532 @@
+use std::{collections::HashMap, error, fmt};
+
+use crate::{
+    ast::{as_fraction, call, is_zero, negate, AST},
+    functions::FunctionRegistry,
+    tokens::BinOp,
+    Error,
+};
+
+#[derive(Debug)]
+pub enum SeriesError {
+    /// `expr` has a pole or branch point at the center, or cancels too much to expand.
+    NotAnalytic { expr: String, point: String },
+}
+impl error::Error for SeriesError {}
+
+impl fmt::Display for SeriesError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            SeriesError::NotAnalytic { expr, point } => {
+                write!(formatter, "`{}` has no Taylor series at {}", expr, point)
+            }
+        };
+    }
+}
+
+/// How many orders beyond the requested one may be computed to make up for cancellation in
+/// quotients such as `sin(x) / x`.
+const MAX_EXTRA_ORDER: usize = 16;
+/// How many points between the center and `x` [`taylor_remainder_bound`] samples.
+const REMAINDER_SAMPLES: usize = 32;
+/// Largest denominator a numeric coefficient is written over: `2^53`, up to which every integer
+/// is exact in an `f64`. That covers `1 / 18!`; coefficients of `sin(x)` or `exp(x)` from
+/// `x ^ 19` on have larger denominators and are written as decimals.
+const MAX_DENOMINATOR: f64 = 9007199254740992.;
+
+/// The Taylor polynomial of `expr` in `var` around `point`, up to and including the term of
+/// degree `order`. `point` may be symbolic, e.g. `pi` or `a`.
+///
+/// Sums, products, quotients and powers are expanded with truncated power series arithmetic,
+/// and functions by composing their series at the inner expression's value, whose
+/// coefficients come from differentiating the function. This lets removable singularities
+/// such as `sin(x) / x` at `0` expand correctly.
+pub fn taylor(expr: &AST, var: &str, point: &AST, order: usize) -> Result<AST, Error> {
+    return taylor_with(expr, var, point, order, FunctionRegistry::builtin());
+}
+
+/// [`taylor`] using the functions in `registry`.
+pub fn taylor_with(
+    expr: &AST,
+    var: &str,
+    point: &AST,
+    order: usize,
+    registry: &FunctionRegistry,
+) -> Result<AST, Error> {
+    let expander = Expander {
+        var,
+        point,
+        registry,
+    };
+    let mut coefficients = None;
+    for extra in 0..=MAX_EXTRA_ORDER {
+        let series = expander.expand(expr, order + extra)?;
+        if series.len() > order {
+            coefficients = Some(series);
+            break;
+        }
+    }
+    let coefficients = coefficients.ok_or_else(|| expander.not_analytic(expr))?;
+    let offset = match point {
+        AST::Literal { value: 0. } => expander.variable(),
+        _ => expander.variable() - point.clone(),
+    };
+    let mut polynomial: Option<AST> = None;
+    for (power, coefficient) in coefficients.into_iter().take(order + 1).enumerate() {
+        if is_zero(&coefficient, expander.registry) {
+            continue;
+        }
+        // Subtract negative terms rather than adding their negation.
+        let (term, negative) = expander.term(coefficient, &offset, power);
+        polynomial = Some(match (polynomial, negative) {
+            (Some(polynomial), true) => polynomial - term,
+            (Some(polynomial), false) => polynomial + term,
+            (None, true) => negate(term),
+            (None, false) => term,
+        });
+    }
+    return Ok(polynomial.unwrap_or(AST::Literal { value: 0. }));
+}
+
+/// An estimate of the Lagrange bound on the error of the order-`order` Taylor polynomial at
+/// `x`: `max |f⁽ⁿ⁺¹⁾(ξ)| |x - point|ⁿ⁺¹ / (n + 1)!` for `ξ` between `point` and `x`. The
+/// maximum is found by sampling the derivative, skipping points where it cannot be evaluated,
+/// so it can be an underestimate for derivatives with narrow peaks.
+pub fn taylor_remainder_bound(
+    expr: &AST,
+    var: &str,
+    point: &AST,
+    order: usize,
+    x: f64,
+) -> Result<f64, Error> {
+    return taylor_remainder_bound_with(expr, var, point, order, x, FunctionRegistry::builtin());
+}
+
+/// [`taylor_remainder_bound`] using the functions in `registry`.
+pub fn taylor_remainder_bound_with(
+    expr: &AST,
+    var: &str,
+    point: &AST,
+    order: usize,
+    x: f64,
+    registry: &FunctionRegistry,
+) -> Result<f64, Error> {
+    let center = point.evaluate_with(&HashMap::new(), registry)?;
+    let derivative = expr.nth_derivative_with(var, order + 1, registry)?;
+    let mut env = HashMap::new();
+    let mut maximum: Option<f64> = None;
+    let mut last_error = None;
+    for sample in 0..=REMAINDER_SAMPLES {
+        let xi = center + (x - center) * sample as f64 / REMAINDER_SAMPLES as f64;
+        env.insert(var.to_string(), xi);
+        match derivative.evaluate_with(&env, registry) {
+            Ok(value) if value.is_finite() => {
+                maximum = Some(maximum.unwrap_or(0.).max(value.abs()));
+            }
+            Ok(_) => (),
+            Err(err) => last_error = Some(err),
+        }
+    }
+    let maximum = match (maximum, last_error) {
+        (Some(maximum), _) => maximum,
+        (None, Some(err)) => return Err(err.into()),
+        (None, None) => return Ok(f64::INFINITY),
+    };
+    let factorial: f64 = (1..=order + 1).map(|k| k as f64).product();
+    return Ok(maximum * (x - center).abs().powi(order as i32 + 1) / factorial);
+}
+
+/// The coefficients of a power series in `var - point`, lowest power first. Only as many are
+/// kept as are known exactly: cancellation in a quotient can leave fewer than were asked for.
+type Series = Vec<AST>;
+
+struct Expander<'a> {
+    var: &'a str,
+    point: &'a AST,
+    registry: &'a FunctionRegistry,
+}
+
+impl Expander<'_> {
+    /// The series of `expr` with at most `order + 1` coefficients.
+    fn expand(&self, expr: &AST, order: usize) -> Result<Series, Error> {
+        let length = order + 1;
+        return expr.try_fold(|node, children: Vec<Series>| -> Result<Series, Error> {
+            if !node.contains_variable(self.var) {
+                return self.constant(node.clone(), length);
+            }
+            let series = match node {
+                AST::Variable { .. } => {
+                    let mut series = self.constant(self.point.clone(), length)?;
+                    if length > 1 {
+                        series[1] = AST::Literal { value: 1. };
+                    }
+                    series
+                }
+                AST::BinOp { op, .. } => {
+                    let (left, right) = (&children[0], &children[1]);
+                    match op {
+                        BinOp::Add => self.combine(left, right, |a, b| a + b)?,
+                        BinOp::Sub => self.combine(left, right, |a, b| a - b)?,
+                        BinOp::Mul => self.multiply(left, right)?,
+                        BinOp::Div => self.divide(node, left, right)?,
+                        BinOp::Pow => self.power(node, left, right, length)?,
+                    }
+                }
+                AST::Function { name, args } if name == "-" && args.len() == 1 => {
+                    self.simplified(children[0].iter().map(|c| negate(c.clone())).collect())?
+                }
+                AST::Function { name, args } if args.len() == 1 => {
+                    self.compose(name, &children[0])?
+                }
+                _ => self.by_derivatives(node, length)?,
+            };
+            let undefined = series.iter().any(|coefficient| match coefficient {
+                AST::Literal { value } => !value.is_finite(),
+                _ => false,
+            });
+            if undefined {
+                return Err(self.not_analytic(node));
+            }
+            return Ok(truncate(series, length));
+        });
+    }
+    fn constant(&self, value: AST, length: usize) -> Result<Series, Error> {
+        let mut series = vec![AST::Literal { value: 0. }; length];
+        series[0] = value;
+        return self.simplified(series);
+    }
+    fn combine(
+        &self,
+        left: &Series,
+        right: &Series,
+        op: impl Fn(AST, AST) -> AST,
+    ) -> Result<Series, Error> {
+        return self.simplified(
+            left.iter()
+                .zip(right)
+                .map(|(a, b)| op(a.clone(), b.clone()))
+                .collect(),
+        );
+    }
+    /// The product is known up to the shorter factor's length, shifted by the other factor's
+    /// leading zeros.
+    fn multiply(&self, left: &Series, right: &Series) -> Result<Series, Error> {
+        let length = (left.len() + self.valuation(right)).min(right.len() + self.valuation(left));
+        let mut product = Vec::with_capacity(length);
+        for k in 0..length {
+            let term = (0..=k)
+                .filter(|i| *i < left.len() && k - i < right.len())
+                .filter(|i| {
+                    !is_zero(&left[*i], self.registry) && !is_zero(&right[k - i], self.registry)
+                })
+                .map(|i| left[i].clone() * right[k - i].clone())
+                .reduce(|sum, term| sum + term)
+                .unwrap_or(AST::Literal { value: 0. });
+            product.push(term);
+        }
+        return self.simplified(product);
+    }
+    /// Cancels the denominator's leading zeros against the numerator's, then divides term by
+    /// term. A numerator with fewer leading zeros means a pole.
+    fn divide(
+        &self,
+        node: &AST,
+        numerator: &Series,
+        denominator: &Series,
+    ) -> Result<Series, Error> {
+        let shift = self.valuation(denominator);
+        if shift == denominator.len() {
+            // Not enough terms to tell where the denominator's leading term is.
+            return Ok(Vec::new());
+        }
+        if self.valuation(numerator) < shift.min(numerator.len()) {
+            return Err(self.not_analytic(node));
+        }
+        let (numerator, denominator) = (
+            &numerator[shift.min(numerator.len())..],
+            &denominator[shift..],
+        );
+        let length = numerator.len().min(denominator.len());
+        let mut quotient: Series = Vec::with_capacity(length);
+        for k in 0..length {
+            let mut term = numerator[k].clone();
+            for j in 1..=k {
+                if !is_zero(&denominator[j], self.registry)
+                    && !is_zero(&quotient[k - j], self.registry)
+                {
+                    term = term - denominator[j].clone() * quotient[k - j].clone();
+                }
+            }
+            let mut term = term / denominator[0].clone();
+            term.simplify_with(self.registry)?;
+            quotient.push(term);
+        }
+        return Ok(quotient);
+    }
+    fn power(
+        &self,
+        node: &AST,
+        base: &Series,
+        exponent: &Series,
+        length: usize,
+    ) -> Result<Series, Error> {
+        let exponent_value = match node {
+            AST::BinOp { right, .. } if !right.contains_variable(self.var) => (**right).clone(),
+            // a^b = exp(b ln(a))
+            _ => {
+                let logarithm = self.compose("ln", base)?;
+                return self.compose("exp", &self.multiply(exponent, &logarithm)?);
+            }
+        };
+        if let AST::Literal { value } = exponent_value {
+            if value >= 0. && value.fract() == 0. {
+                let mut power = self.constant(AST::Literal { value: 1. }, length)?;
+                for _ in 0..value as usize {
+                    power = truncate(self.multiply(&power, base)?, length);
+                }
+                return Ok(power);
+            }
+        }
+        if self.valuation(base) > 0 {
+            return Err(self.not_analytic(node));
+        }
+        // The coefficients of p = b^n satisfy b p' = n b' p, which gives
+        // p_k = Σ_{j=1..k} ((n + 1) j - k) b_j p_{k-j} / (k b_0).
+        let mut power: Series = Vec::with_capacity(base.len());
+        power.push(base[0].clone().pow(exponent_value.clone()));
+        for k in 1..base.len() {
+            let mut sum: Option<AST> = None;
+            for j in 1..=k {
+                if is_zero(&base[j], self.registry) {
+                    continue;
+                }
+                let weight = (exponent_value.clone() + AST::Literal { value: 1. })
+                    * AST::Literal { value: j as f64 }
+                    - AST::Literal { value: k as f64 };
+                let term = weight * base[j].clone() * power[k - j].clone();
+                sum = Some(match sum {
+                    Some(sum) => sum + term,
+                    None => term,
+                });
+            }
+            let mut coefficient = match sum {
+                Some(sum) => sum / (AST::Literal { value: k as f64 } * base[0].clone()),
+                None => AST::Literal { value: 0. },
+            };
+            coefficient.simplify_with(self.registry)?;
+            power.push(coefficient);
+        }
+        return Ok(power);
+    }
+    /// `f(g)` as `Σ f⁽ᵏ⁾(g₀) / k! (g - g₀)ᵏ`, where `g₀` is the constant term of `g`.
+    fn compose(&self, name: &str, inner: &Series) -> Result<Series, Error> {
+        if inner.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut offset = inner.clone();
+        offset[0] = AST::Literal { value: 0. };
+        let u = "u";
+        let mut derivative = call(
+            name,
+            AST::Variable {
+                name: u.to_string(),
+            },
+        );
+        let mut sum = self.constant(AST::Literal { value: 0. }, inner.len())?;
+        let mut power = self.constant(AST::Literal { value: 1. }, inner.len())?;
+        let mut factorial = 1.;
+        for k in 0..inner.len() {
+            if k > 0 {
+                derivative = derivative.nth_derivative_with(u, 1, self.registry)?;
+                power = self.multiply(&power, &offset)?;
+                factorial *= k as f64;
+            }
+            let mut coefficient =
+                derivative.substitute(u, &inner[0]) / AST::Literal { value: factorial };
+            coefficient.simplify_with(self.registry)?;
+            if is_zero(&coefficient, self.registry) {
+                continue;
+            }
+            let term: Series = power
+                .iter()
+                .map(|p| coefficient.clone() * p.clone())
+                .collect();
+            sum = self.combine(&sum, &term, |a, b| a + b)?;
+        }
+        return Ok(sum);
+    }
+    /// Falls back to `f⁽ᵏ⁾(point) / k!` for calls of several arguments.
+    fn by_derivatives(&self, node: &AST, length: usize) -> Result<Series, Error> {
+        let mut series = Vec::with_capacity(length);
+        let mut derivative = node.clone();
+        let mut factorial = 1.;
+        for k in 0..length {
+            if k > 0 {
+                derivative = derivative.nth_derivative_with(self.var, 1, self.registry)?;
+                factorial *= k as f64;
+            }
+            series.push(
+                derivative.substitute(self.var, self.point) / AST::Literal { value: factorial },
+            );
+        }
+        return self.simplified(series);
+    }
+    /// The size of `coefficient * offset ^ power` and whether it is negative. The numeric
+    /// factor of the coefficient is written as a fraction `p / q` when it is one with `q` up to
+    /// [`MAX_DENOMINATOR`], giving `p * rest * offset ^ power / q`, and as a decimal otherwise.
+    fn term(&self, coefficient: AST, offset: &AST, power: usize) -> (AST, bool) {
+        let (scale, numerators, denominators) = split_numeric(coefficient);
+        let (numerator, denominator) =
+            as_fraction(scale.abs(), MAX_DENOMINATOR).unwrap_or((scale.abs(), 1.));
+        let monomial = match power {
+            0 => None,
+            1 => Some(offset.clone()),
+            _ => Some(offset.clone().pow(AST::Literal {
+                value: power as f64,
+            })),
+        };
+        let mut top = Vec::new();
+        if numerator != 1. || (numerators.is_empty() && monomial.is_none()) {
+            top.push(AST::Literal { value: numerator });
+        }
+        top.extend(numerators);
+        top.extend(monomial);
+        let mut bottom = Vec::new();
+        if denominator != 1. {
+            bottom.push(AST::Literal { value: denominator });
+        }
+        bottom.extend(denominators);
+        let top = top.into_iter().reduce(|product, factor| product * factor);
+        let term = match bottom
+            .into_iter()
+            .reduce(|product, factor| product * factor)
+        {
+            Some(bottom) => top.unwrap() / bottom,
+            None => top.unwrap(),
+        };
+        return (term, scale < 0.);
+    }
+    fn simplified(&self, mut series: Series) -> Result<Series, Error> {
+        for coefficient in series.iter_mut() {
+            coefficient.simplify_with(self.registry)?;
+        }
+        return Ok(series);
+    }
+    /// Number of leading zero coefficients.
+    fn valuation(&self, series: &Series) -> usize {
+        return series
+            .iter()
+            .position(|coefficient| !is_zero(coefficient, self.registry))
+            .unwrap_or(series.len());
+    }
+    fn variable(&self) -> AST {
+        return AST::Variable {
+            name: self.var.to_string(),
+        };
+    }
+    fn not_analytic(&self, expr: &AST) -> Error {
+        return SeriesError::NotAnalytic {
+            expr: expr.to_string(),
+            point: format!("{} = {}", self.var, self.point),
+        }
+        .into();
+    }
+}
+
+/// Splits the `*` and `/` chain of `coefficient` into the product of its numeric factors and
+/// the other factors above and below the line.
+fn split_numeric(coefficient: AST) -> (f64, Vec<AST>, Vec<AST>) {
+    let mut scale = 1.;
+    let (mut numerators, mut denominators) = (Vec::new(), Vec::new());
+    let mut pending = vec![(coefficient, false)];
+    while let Some((factor, inverted)) = pending.pop() {
+        match factor {
+            AST::Literal { value } if inverted => scale /= value,
+            AST::Literal { value } => scale *= value,
+            AST::BinOp {
+                op: op @ (BinOp::Mul | BinOp::Div),
+                ..
+            } => {
+                let mut operands = factor.into_children();
+                let right = operands.pop().unwrap();
+                pending.push((right, inverted != (op == BinOp::Div)));
+                pending.push((operands.pop().unwrap(), inverted));
+            }
+            AST::Function { ref name, ref args } if name == "-" && args.len() == 1 => {
+                scale = -scale;
+                pending.push((factor.into_children().remove(0), inverted));
+            }
+            _ if inverted => denominators.push(factor),
+            _ => numerators.push(factor),
+        }
+    }
+    return (scale, numerators, denominators);
+}
+
+fn truncate(mut series: Series, length: usize) -> Series {
+    series.truncate(length);
+    return series;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{taylor, taylor_remainder_bound};
+    use crate::{parse_str, AST};
+
+    fn series(source: &str, point: &str, order: usize) -> String {
+        let expr = parse_str(source).unwrap();
+        let point = parse_str(point).unwrap();
+        return taylor(&expr, "x", &point, order).unwrap().to_string();
+    }
+
+    #[test]
+    fn coefficients_are_written_as_fractions() {
+        assert_eq!(
+            series("exp(x)", "0", 6),
+            "1 + x + x ^ 2 / 2 + x ^ 3 / 6 + x ^ 4 / 24 + x ^ 5 / 120 + x ^ 6 / 720"
+        );
+        assert_eq!(
+            series("exp(a * x)", "0", 5),
+            "1 + a * x + a ^ 2 * x ^ 2 / 2 + a ^ 3 * x ^ 3 / 6 + a ^ 4 * x ^ 4 / 24 \
+             + a ^ 5 * x ^ 5 / 120"
+        );
+        assert_eq!(
+            series("sin(x)", "0.3", 2),
+            "sin(0.3) + cos(0.3) * (x - 0.3) - sin(0.3) * (x - 0.3) ^ 2 / 2"
+        );
+    }
+
+    #[test]
+    fn large_factorials_are_written_as_decimals() {
+        let sine = series("sin(x)", "0", 40);
+        assert!(sine.contains(
+            " + x ^ 17 / 355687428096000 - 0.00000000000000000822063524662433 * x ^ 19 + "
+        ));
+        assert_eq!(sine.matches(" / ").count(), 8);
+    }
+
+    #[test]
+    fn removable_singularities_and_symbolic_centers() {
+        assert_eq!(
+            series("sin(x) / x", "0", 6),
+            "1 - x ^ 2 / 6 + x ^ 4 / 120 - x ^ 6 / 5040"
+        );
+        assert_eq!(series("sin(x)", "pi", 3), "-(x - pi) + (x - pi) ^ 3 / 6");
+        assert_eq!(series("1 / (1 - x)", "0", 3), "1 + x + x ^ 2 + x ^ 3");
+    }
+
+    #[test]
+    fn poles_have_no_series() {
+        let expr = parse_str("1 / x").unwrap();
+        assert!(taylor(&expr, "x", &AST::Literal { value: 0. }, 3).is_err());
+    }
+
+    #[test]
+    fn remainder_bound_covers_the_error() {
+        let expr = parse_str("exp(x)").unwrap();
+        let zero = AST::Literal { value: 0. };
+        let bound = taylor_remainder_bound(&expr, "x", &zero, 3, 1.).unwrap();
+        let error = 1f64.exp() - (1. + 1. + 0.5 + 1. / 6.);
+        assert!(error <= bound && bound <= 1f64.exp() / 24. + 1e-12);
+    }
+}
@@ -1,25 +1,50 @@
-use std::{error::Error, fmt};
+use std::{collections::HashSet, error::Error, fmt, mem, rc::Rc};
 
-use crate::{ast::AST, tokens::BinOp};
+use crate::{
+    ast::AST, collector::collect, dag::Interner, functions::FunctionRegistry, tokens::BinOp,
+};
 
 #[derive(Debug)]
 pub enum EvaluationError {
     ZeroDivisionError,
-    WrongArguments
+    WrongArguments,
+    UnboundVariable(String),
+    UnknownConstant(String),
+    UnknownFunction(String),
+    DomainError { function: String, argument: f64 },
 }
 impl Error for EvaluationError {}
 
 impl fmt::Display for EvaluationError {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        return write!(formatter, "rip");
+        return match self {
+            EvaluationError::ZeroDivisionError => write!(formatter, "division by zero"),
+            EvaluationError::WrongArguments => write!(formatter, "wrong number of arguments"),
+            EvaluationError::UnboundVariable(name) => {
+                write!(formatter, "no value bound to variable `{}`", name)
+            }
+            EvaluationError::UnknownConstant(name) => {
+                write!(formatter, "unknown constant `{}`", name)
+            }
+            EvaluationError::UnknownFunction(name) => {
+                write!(formatter, "unknown function `{}`", name)
+            }
+            EvaluationError::DomainError { function, argument } => {
+                write!(formatter, "{} is undefined at {}", function, argument)
+            }
+        };
     }
 }
 
 impl AST {
     pub fn simplify(&mut self) -> Result<bool, EvaluationError> {
+        return self.simplify_with(FunctionRegistry::builtin());
+    }
+    /// [`AST::simplify`] using the special values of the functions in `registry`.
+    pub fn simplify_with(&mut self, registry: &FunctionRegistry) -> Result<bool, EvaluationError> {
         let mut simplified = false;
         loop {
-            let changed = self.simplify_once()?;
+            let changed = self.simplify_once(registry)?;
             if changed {
                 simplified = true;
                 continue;
@@ -28,29 +53,45 @@ impl AST {
         }
         return Ok(simplified);
     }
-    fn simplify_once(&mut self) -> Result<bool, EvaluationError> {
+    /// One bottom-up pass over the tree, rebuilding every node from its simplified children
+    /// and then applying the local rules to it. Rebuilt nodes are interned, so repeated
+    /// subexpressions are simplified once and stay shared.
+    fn simplify_once(&mut self, registry: &FunctionRegistry) -> Result<bool, EvaluationError> {
+        let mut changed = false;
+        let mut interner = Interner::default();
+        let roots = chain_roots(self);
+        let simplified = self.try_fold(|node, children: Vec<Rc<AST>>| {
+            let is_root = roots.contains(&(node as *const AST));
+            let mut node = node.with_children(children);
+            changed |= node.simplify_node(registry, is_root)?;
+            return Ok(interner.intern(node));
+        })?;
+        *self = Rc::unwrap_or_clone(simplified);
+        return Ok(changed);
+    }
+    /// Applies the local rules to a node whose children are already simplified. Like terms
+    /// are collected only at the root of a chain, which gathers the whole chain at once.
+    fn simplify_node(
+        &mut self,
+        registry: &FunctionRegistry,
+        is_root: bool,
+    ) -> Result<bool, EvaluationError> {
         match self {
             AST::Literal { .. } | AST::SymbolicConstant { .. } | AST::Variable { .. } => {
                 return Ok(false)
             }
-            AST::Function { name, args } => {
-                let mut simplified_args = false;
-                for arg in args.iter_mut() {
-                    simplified_args = arg.simplify()? | simplified_args;
-                }
-                match get_standard_value(name, args)? {
-                    Some(value) => *self = value,
-                    _ => (),
+            AST::Function { name, args } => match get_standard_value(name, args, registry)? {
+                Some(value) => {
+                    *self = value;
+                    return Ok(true);
                 }
-                return Ok(simplified_args);
-            }
+                _ => return Ok(false),
+            },
             AST::BinOp { op, left, right } => {
-                left.simplify()?;
-                right.simplify()?;
-                match (*left.clone(), *right.clone()) {
+                match (&**left, &**right) {
                     (AST::Literal { value: val1 }, AST::Literal { value: val2 }) => {
                         *self = AST::Literal {
-                            value: get_func_from_op(*op)(val1, val2),
+                            value: get_func_from_op(*op)(*val1, *val2),
                         };
                         return Ok(true);
                     }
@@ -61,7 +102,14 @@ impl AST {
                         match **left {
                             AST::Literal { value } => {
                                 if value == 0. {
-                                    *self = *right.clone();
+                                    if *op == BinOp::Sub {
+                                        *self = AST::Function {
+                                            name: "-".to_string(),
+                                            args: vec![Rc::clone(right)],
+                                        };
+                                    } else {
+                                        self.replace_with_operand(1);
+                                    }
                                     return Ok(true);
                                 }
                             }
@@ -70,19 +118,19 @@ impl AST {
                         match **right {
                             AST::Literal { value } => {
                                 if value == 0. {
-                                    *self = *left.clone();
+                                    self.replace_with_operand(0);
                                     return Ok(true);
                                 }
                             }
                             _ => (),
                         }
-                        return Ok(false);
+                        return self.collect_terms(registry, is_root);
                     }
                     BinOp::Mul => {
                         match **left {
                             AST::Literal { value } => {
                                 if value == 1. {
-                                    *self = *right.clone();
+                                    self.replace_with_operand(1);
                                     return Ok(true);
                                 } else if value == 0. {
                                     *self = AST::Literal { value: 0. };
@@ -94,7 +142,7 @@ impl AST {
                         match **right {
                             AST::Literal { value } => {
                                 if value == 1. {
-                                    *self = *left.clone();
+                                    self.replace_with_operand(0);
                                     return Ok(true);
                                 } else if value == 0. {
                                     *self = AST::Literal { value: 0. };
@@ -103,7 +151,7 @@ impl AST {
                             }
                             _ => (),
                         }
-                        return Ok(false);
+                        return self.collect_terms(registry, is_root);
                     }
                     BinOp::Div => {
                         match **left {
@@ -118,7 +166,7 @@ impl AST {
                         match **right {
                             AST::Literal { value } => {
                                 if value == 1. {
-                                    *self = *left.clone();
+                                    self.replace_with_operand(0);
                                     return Ok(true);
                                 } else if value == 0. {
                                     return Err(EvaluationError::ZeroDivisionError);
@@ -126,7 +174,7 @@ impl AST {
                             }
                             _ => (),
                         }
-                        return Ok(false);
+                        return self.collect_terms(registry, is_root);
                     }
                     BinOp::Pow => {
                         match **left {
@@ -144,7 +192,7 @@ impl AST {
                                     *self = AST::Literal { value: 1. };
                                     return Ok(true);
                                 } else if value == 1. {
-                                    *self = *left.clone();
+                                    self.replace_with_operand(0);
                                     return Ok(true);
                                 }
                             }
@@ -157,6 +205,73 @@ impl AST {
         }
     }
 }
+impl AST {
+    /// Replaces a binary operation by one of its operands without copying it.
+    fn replace_with_operand(&mut self, index: usize) {
+        let operand = mem::replace(self, AST::Literal { value: 0. })
+            .into_children()
+            .swap_remove(index);
+        *self = operand;
+    }
+    fn collect_terms(
+        &mut self,
+        registry: &FunctionRegistry,
+        is_root: bool,
+    ) -> Result<bool, EvaluationError> {
+        if !is_root {
+            return Ok(false);
+        }
+        return match collect(self, registry)? {
+            Some(collected) if collected != *self => {
+                *self = collected;
+                Ok(true)
+            }
+            _ => Ok(false),
+        };
+    }
+}
+
+/// Which chain [`collect`] flattens a node into: sums and differences, or products and
+/// quotients.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Chain {
+    Sum,
+    Product,
+}
+
+fn chain(ast: &AST) -> Option<Chain> {
+    return match ast {
+        AST::BinOp {
+            op: BinOp::Add | BinOp::Sub,
+            ..
+        } => Some(Chain::Sum),
+        AST::BinOp {
+            op: BinOp::Mul | BinOp::Div,
+            ..
+        } => Some(Chain::Product),
+        _ => None,
+    };
+}
+
+/// The nodes that start a maximal chain of `+`/`-` or `*`/`/`, that is, the ones with some
+/// parent outside their chain. A shared node is visited once per kind of parent.
+fn chain_roots(ast: &AST) -> HashSet<*const AST> {
+    let mut roots = HashSet::new();
+    let mut visited = HashSet::new();
+    let mut pending = vec![(ast, None)];
+    while let Some((node, parent)) = pending.pop() {
+        if !visited.insert((node as *const AST, parent)) {
+            continue;
+        }
+        let kind = chain(node);
+        if kind.is_some() && kind != parent {
+            roots.insert(node as *const AST);
+        }
+        pending.extend(node.children().into_iter().map(|child| (child, kind)));
+    }
+    return roots;
+}
+
 fn get_func_from_op(op: BinOp) -> fn(f64, f64) -> f64 {
     return match op {
         BinOp::Add => |x, y| x + y,
@@ -167,34 +282,40 @@ fn get_func_from_op(op: BinOp) -> fn(f64, f64) -> f64 {
     };
 }
 
-fn get_standard_value(name: &str, args: &Vec<AST>) -> Result<Option<AST>, EvaluationError> {
-    // match value[..] {
-    //     AST::Literal { .. } | AST::SymbolicConstant { .. } => (),
-    //     _ => return None,
-    // // }
-    // if !args
-    //     .iter()
-    //     .all(|arg| matches!(arg, AST::Literal { .. } | AST::SymbolicConstant { .. }))
-    // {
-    //     return None;
-    // }
-    match name {
-        "sin" => match &args[..] {
-            [AST::Literal { value }] if *value == 0. => return Ok(Some(AST::Literal { value: 0. })),
-            [AST::SymbolicConstant { name }] if name == "pi" => {
-                return Ok(Some(AST::Literal { value: 0. }))
-            }
-            [_] => return Ok(None),
-            _ => return Err(EvaluationError::WrongArguments)
-        },
-        "ln" => match &args[..] {
-            [AST::Literal { value }] if *value == 0. => return Ok(Some(AST::Literal { value: 0. })),
-            [AST::SymbolicConstant { name }] if name == "e" => {
-                return Ok(Some(AST::Literal { value: 1. }))
-            }
-            [_] => return Ok(None),
-            _ => return Err(EvaluationError::WrongArguments)
-        },
-        _ => return Ok(None),
+fn get_standard_value(
+    name: &str,
+    args: &[Rc<AST>],
+    registry: &FunctionRegistry,
+) -> Result<Option<AST>, EvaluationError> {
+    return match registry.get(name) {
+        Some(definition) if definition.arity() != args.len() => {
+            Err(EvaluationError::WrongArguments)
+        }
+        Some(definition) => {
+            let args: Vec<AST> = args.iter().map(|arg| (**arg).clone()).collect();
+            Ok(definition.special_value(&args))
+        }
+        None => Ok(None),
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{differentiate_str, parse_str};
+
+    #[test]
+    fn zero_minus_operand_keeps_the_sign() {
+        let mut ast = parse_str("0 - x").unwrap();
+        ast.simplify().unwrap();
+        assert_eq!(ast.to_string(), "-(x)");
+    }
+
+    #[test]
+    fn derivative_of_reciprocal_is_negative() {
+        let derivative = differentiate_str("1 / (x^2 + 1)").unwrap();
+        let env = HashMap::from([("x".to_string(), 1.)]);
+        assert_eq!(derivative.evaluate(&env).unwrap(), -0.5);
+    }
+}
@@ -0,0 +1,48 @@
+/// Pads the cells of `rows` to the width of the widest cell in their column and joins each row
+/// with two spaces. Missing cells at the end of short rows count as empty, so every line comes
+/// out the same width.
+pub(crate) fn align_columns(rows: &[Vec<String>]) -> Vec<String> {
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let widths: Vec<usize> = (0..columns)
+        .map(|column| {
+            rows.iter()
+                .filter_map(|row| row.get(column))
+                .map(|cell| cell.chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    return rows
+        .iter()
+        .map(|row| {
+            widths
+                .iter()
+                .enumerate()
+                .map(|(column, width)| {
+                    let cell = row.get(column).map_or("", String::as_str);
+                    format!("{}{}", cell, " ".repeat(width - cell.chars().count()))
+                })
+                .collect::<Vec<String>>()
+                .join("  ")
+        })
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::align_columns;
+
+    #[test]
+    fn pads_every_column_to_its_widest_cell() {
+        let rows = vec![
+            vec!["x".to_string(), "f(x)".to_string()],
+            vec!["-1.5".to_string(), "2".to_string()],
+            vec!["0".to_string()],
+        ];
+        assert_eq!(
+            align_columns(&rows),
+            ["x     f(x)", "-1.5  2   ", "0         "]
+        );
+        assert!(align_columns(&[]).is_empty());
+    }
+}
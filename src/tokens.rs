@@ -1,7 +1,6 @@
 use std::fmt::{Debug, Display};
 
-
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
 // #[derive(Debug, Clone, Copy)]
 pub enum BinOp {
     Sub,
@@ -23,7 +22,6 @@ impl Display for BinOp {
     }
 }
 
-
 #[derive(Debug)]
 pub enum Token {
     OpenParen,
@@ -34,4 +32,39 @@ pub enum Token {
     Function(String),
     BinOp(BinOp),
     Comma,
+    /// The `√` prefix, read as `sqrt` of the operand that follows.
+    Radical,
+    /// The `=` between the two sides of an equation.
+    Equals,
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::OpenParen => write!(f, "("),
+            Self::CloseParen => write!(f, ")"),
+            Self::Literal(value) => write!(f, "{}", value),
+            Self::SymbolicConstant(name) | Self::Variable(name) | Self::Function(name) => {
+                write!(f, "{}", name)
+            }
+            Self::BinOp(op) => write!(f, "{}", op),
+            Self::Comma => write!(f, ","),
+            Self::Radical => write!(f, "√"),
+            Self::Equals => write!(f, "="),
+        }
+    }
+}
+
+/// A range of byte offsets into the source text.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A token together with where it was read from.
+#[derive(Debug)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
 }